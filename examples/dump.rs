@@ -11,16 +11,20 @@ fn main() -> Result<()> {
 
     let mut parser = sloppy_rfc4880::Parser::new(buf.as_slice());
 
-    while let Some((tag, body)) = parser.next() {
-        let body = bytes::Bytes::from(body.clone());
+    while let Some((tag, body)) = parser.next_packet()? {
+        let body = bytes::Bytes::from(body);
         println!("{:?}: {:?}", tag, body);
         match tag {
-            Tag::PublicKey => {
-                let fp = pubkey::fingerprint(&*body);
+            Tag::PublicKey | Tag::PublicSubkey => {
+                let fp = pubkey::fingerprint(&body)
+                    .context("pubkey::fingerprint")?;
                 println!("\tfingerprint: {:?}", fp);
+                let key = pubkey::parse(&body)
+                    .context("pubkey::parse")?;
+                println!("\talgorithm: {:?}, bits: {:?}", key.algorithm, key.bits());
             },
             Tag::Signature => {
-                let issuer = signature::parse(&*body)
+                let issuer = signature::parse(&body)
                     .context("signature::parse")?;
                 println!("\tissuer: {:?}", issuer);
             },
@@ -0,0 +1,32 @@
+use sloppy_rfc4880::errors::*;
+use sloppy_rfc4880::keyring::KeyringParser;
+use std::io;
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let stdin = io::stdin();
+    let parser = KeyringParser::with_max_alloc(stdin.lock(), 1024 * 1024);
+
+    for cert in parser {
+        let cert = match cert {
+            Ok(cert) => cert,
+            Err(err) => {
+                eprintln!("Error: {:#}", err);
+                continue;
+            },
+        };
+
+        let fp = cert.primary.fingerprint()
+            .context("Failed to calculate fingerprint")?;
+        println!("{}", fp);
+        for uid in &cert.userids {
+            println!("\t{}", uid.userid);
+        }
+        for violation in &cert.violations {
+            info!("{}: {}", fp, violation);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use serde::{Serialize, Deserialize};
+
+// https://tools.ietf.org/html/rfc4880#section-9.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PublicKeyAlgorithm {
+    Rsa,
+    RsaEncryptOnly,
+    RsaSignOnly,
+    Elgamal,
+    Dsa,
+    Ecdh,
+    Ecdsa,
+    ElgamalEncryptOrSign,
+    Eddsa,
+    Unknown(u8),
+}
+
+impl PublicKeyAlgorithm {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            1 => PublicKeyAlgorithm::Rsa,
+            2 => PublicKeyAlgorithm::RsaEncryptOnly,
+            3 => PublicKeyAlgorithm::RsaSignOnly,
+            16 => PublicKeyAlgorithm::Elgamal,
+            17 => PublicKeyAlgorithm::Dsa,
+            18 => PublicKeyAlgorithm::Ecdh,
+            19 => PublicKeyAlgorithm::Ecdsa,
+            20 => PublicKeyAlgorithm::ElgamalEncryptOrSign,
+            22 => PublicKeyAlgorithm::Eddsa,
+            b => PublicKeyAlgorithm::Unknown(b),
+        }
+    }
+}
+
+// https://tools.ietf.org/html/rfc4880#section-9.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymmetricAlgorithm {
+    Plaintext,
+    Idea,
+    TripleDes,
+    Cast5,
+    Blowfish,
+    Aes128,
+    Aes192,
+    Aes256,
+    Twofish,
+    Camellia128,
+    Camellia192,
+    Camellia256,
+    Unknown(u8),
+}
+
+impl SymmetricAlgorithm {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            0 => SymmetricAlgorithm::Plaintext,
+            1 => SymmetricAlgorithm::Idea,
+            2 => SymmetricAlgorithm::TripleDes,
+            3 => SymmetricAlgorithm::Cast5,
+            4 => SymmetricAlgorithm::Blowfish,
+            7 => SymmetricAlgorithm::Aes128,
+            8 => SymmetricAlgorithm::Aes192,
+            9 => SymmetricAlgorithm::Aes256,
+            10 => SymmetricAlgorithm::Twofish,
+            11 => SymmetricAlgorithm::Camellia128,
+            12 => SymmetricAlgorithm::Camellia192,
+            13 => SymmetricAlgorithm::Camellia256,
+            b => SymmetricAlgorithm::Unknown(b),
+        }
+    }
+}
+
+// https://tools.ietf.org/html/rfc4880#section-9.4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Ripemd160,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha224,
+    Sha3_256,
+    Sha3_512,
+    Unknown(u8),
+}
+
+impl HashAlgorithm {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            1 => HashAlgorithm::Md5,
+            2 => HashAlgorithm::Sha1,
+            3 => HashAlgorithm::Ripemd160,
+            8 => HashAlgorithm::Sha256,
+            9 => HashAlgorithm::Sha384,
+            10 => HashAlgorithm::Sha512,
+            11 => HashAlgorithm::Sha224,
+            12 => HashAlgorithm::Sha3_256,
+            14 => HashAlgorithm::Sha3_512,
+            b => HashAlgorithm::Unknown(b),
+        }
+    }
+}
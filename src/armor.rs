@@ -1,40 +1,344 @@
 use crate::errors::*;
 use std::io::prelude::*;
 
+// https://tools.ietf.org/html/rfc4880#section-6.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockType {
+    PublicKey,
+    PrivateKey,
+    Signature,
+    Message,
+    /// Any other label, like `MESSAGE, PART 1/2`
+    Other(String),
+}
+
+impl BlockType {
+    pub fn from_label(label: &str) -> BlockType {
+        match label {
+            "PUBLIC KEY BLOCK" => BlockType::PublicKey,
+            // used by old versions of gnupg
+            "PRIVATE KEY BLOCK" | "SECRET KEY BLOCK" => BlockType::PrivateKey,
+            "SIGNATURE" => BlockType::Signature,
+            "MESSAGE" => BlockType::Message,
+            _ => BlockType::Other(label.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            BlockType::PublicKey => "PUBLIC KEY BLOCK",
+            BlockType::PrivateKey => "PRIVATE KEY BLOCK",
+            BlockType::Signature => "SIGNATURE",
+            BlockType::Message => "MESSAGE",
+            BlockType::Other(label) => label,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Armor {
+    pub block_type: BlockType,
+    pub headers: Vec<(String, String)>,
+    pub data: Vec<u8>,
+    pub checksum: Checksum,
+}
+
+impl Armor {
+    /// Return the value of the first header with this key, like `Comment`
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+const CRC24_INIT: u32 = 0xb7_04ce;
+const CRC24_POLY: u32 = 0x186_4cfb;
+
+// https://tools.ietf.org/html/rfc4880#section-6.1
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for b in data {
+        crc ^= (*b as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xff_ffff
+}
+
+fn encode_crc24(data: &[u8]) -> String {
+    let crc = crc24(data).to_be_bytes();
+    base64::encode(&crc[1..])
+}
+
+pub fn write_armored<W: Write>(w: &mut W, block_type: &BlockType, headers: &[(&str, &str)], data: &[u8]) -> Result<()> {
+    let label = block_type.as_str();
+    if label.contains('-') || label.contains('\n') {
+        bail!("Invalid armor label: {:?}", label);
+    }
+
+    writeln!(w, "-----BEGIN PGP {}-----", label)?;
+    for (key, value) in headers {
+        if key.contains(':') || key.contains('\n') || value.contains('\n') {
+            bail!("Invalid armor header: {:?}", key);
+        }
+        writeln!(w, "{}: {}", key, value)?;
+    }
+    writeln!(w)?;
+
+    let content = base64::encode(data);
+    for line in content.as_bytes().chunks(64) {
+        w.write_all(line)?;
+        writeln!(w)?;
+    }
+
+    writeln!(w, "={}", encode_crc24(data))?;
+    writeln!(w, "-----END PGP {}-----", label)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// The armor didn't contain a checksum line
+    Missing,
+    Valid,
+    Mismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The checksum line isn't valid base64 or has the wrong length
+    Malformed,
+}
+
+impl Checksum {
+    fn verify(data: &[u8], line: Option<&str>) -> Checksum {
+        let line = match line {
+            Some(line) => line,
+            None => return Checksum::Missing,
+        };
+
+        let crc = match base64::decode(line) {
+            Ok(crc) if crc.len() == 3 => crc,
+            _ => {
+                debug!("Malformed armor checksum: {:?}", line);
+                return Checksum::Malformed;
+            },
+        };
+        let expected = u32::from_be_bytes([0, crc[0], crc[1], crc[2]]);
+        let actual = crc24(data);
+
+        if expected == actual {
+            Checksum::Valid
+        } else {
+            Checksum::Mismatch {
+                expected,
+                actual,
+            }
+        }
+    }
+}
+
 pub fn read_armored<R: BufRead>(r: &mut R) -> Result<Vec<u8>> {
+    let armor = read_armor(r)?;
+    Ok(armor.data)
+}
+
+/// Unarmor and fail if the checksum doesn't match the decoded data or can't
+/// be decoded. A missing checksum is accepted since it's optional.
+pub fn read_armored_strict<R: BufRead>(r: &mut R) -> Result<Vec<u8>> {
+    let armor = read_armor(r)?;
+    match armor.checksum {
+        Checksum::Mismatch { expected, actual } => {
+            bail!("Armor checksum mismatch (expected {:06X}, got {:06X})", expected, actual);
+        },
+        Checksum::Malformed => bail!("Armor checksum is malformed"),
+        Checksum::Missing | Checksum::Valid => (),
+    }
+    Ok(armor.data)
+}
+
+/// Unarmor and report the result of the checksum verification to the caller.
+pub fn read_armored_lenient<R: BufRead>(r: &mut R) -> Result<(Vec<u8>, Checksum)> {
+    let armor = read_armor(r)?;
+    Ok((armor.data, armor.checksum))
+}
+
+fn parse_begin_line(line: &str) -> Option<BlockType> {
+    let label = line.strip_prefix("-----BEGIN PGP ")?
+        .strip_suffix("-----")?;
+    Some(BlockType::from_label(label))
+}
+
+fn read_line<R: BufRead>(r: &mut R, buf: &mut String, offset: &mut u64) -> Result<bool> {
+    buf.clear();
+    let n = r.read_line(buf)?;
+    *offset += n as u64;
+    Ok(n > 0)
+}
+
+/// Skip to the next BEGIN line, returning its offset and block type
+fn find_begin<R: BufRead>(r: &mut R, offset: &mut u64) -> Result<Option<(u64, BlockType)>> {
+    let mut buf = String::new();
+    loop {
+        let start = *offset;
+        if !read_line(r, &mut buf, offset)? {
+            return Ok(None);
+        }
+
+        if let Some(block_type) = parse_begin_line(buf.trim_end()) {
+            return Ok(Some((start, block_type)));
+        }
+    }
+}
+
+/// A BEGIN line was found before the END line of the current block, the
+/// BEGIN line has already been consumed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedBegin {
+    pub offset: u64,
+    pub block_type: BlockType,
+}
+
+impl std::fmt::Display for UnexpectedBegin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unexpected BEGIN line inside of armor")
+    }
+}
+
+impl std::error::Error for UnexpectedBegin {}
+
+/// Read the headers, contents and checksum of an armored block, up to and
+/// including the END line. The BEGIN line has already been consumed.
+pub(crate) fn read_armor_body<R: BufRead>(r: &mut R, block_type: BlockType, offset: &mut u64) -> Result<Armor> {
     let mut buf = String::new();
     let mut content = String::new();
-    let mut armor_started = false;
+    let mut headers = Vec::new();
+    let mut checksum = None;
     let mut contents_started = false;
 
     loop {
-        buf.clear();
-        let n = r.read_line(&mut buf)?;
-        if n == 0 {
+        let start = *offset;
+        if !read_line(r, &mut buf, offset)? {
             break;
         }
-
         let tr = buf.trim_end();
 
-        if tr.starts_with("-----BEGIN PGP ") && tr.ends_with("-----") {
-            armor_started = true
-        } else if tr.starts_with("-----END PGP ") && tr.ends_with("-----") {
+        if tr.starts_with("-----END PGP ") && tr.ends_with("-----") {
             break;
-        } else if armor_started {
-            if contents_started {
-                if buf.starts_with('=') {
-                    contents_started = false
-                } else {
-                    content += tr
-                }
-            } else if tr == "" {
-                contents_started = true
+        } else if let Some(block_type) = parse_begin_line(tr) {
+            return Err(UnexpectedBegin {
+                offset: start,
+                block_type,
+            }.into());
+        } else if contents_started {
+            if let Some(crc) = tr.strip_prefix('=') {
+                checksum = Some(crc.to_string());
+                contents_started = false
+            } else {
+                content += tr
             }
+        } else if tr.is_empty() {
+            contents_started = true
+        } else if let Some((key, value)) = tr.split_once(':') {
+            headers.push((key.to_string(), value.trim_start().to_string()));
+        } else {
+            debug!("Ignoring invalid armor header: {:?}", tr);
         }
     }
 
-    base64::decode(&content)
-        .map_err(Error::from)
+    let data = base64::decode(&content)?;
+    let checksum = Checksum::verify(&data, checksum.as_deref());
+    debug!("Armor checksum: {:?}", checksum);
+
+    Ok(Armor {
+        block_type,
+        headers,
+        data,
+        checksum,
+    })
+}
+
+/// Unarmor the first armored block, including the block type and headers.
+pub fn read_armor<R: BufRead>(r: &mut R) -> Result<Armor> {
+    let mut offset = 0;
+    let (_, block_type) = find_begin(r, &mut offset)?
+        .context("Failed to find beginning of armor")?;
+    read_armor_body(r, block_type, &mut offset)
+}
+
+/// Iterate over all armored blocks in a stream, like a file containing
+/// multiple concatenated public keys. Yields the offset of the BEGIN line
+/// together with each block. A block that fails to decode is yielded as an
+/// error and the reader continues with the next one, only I/O errors end
+/// the iteration.
+pub struct ArmorReader<R: BufRead> {
+    r: R,
+    offset: u64,
+    /// BEGIN line that was consumed while reading the previous block
+    pending: Option<(u64, BlockType)>,
+    done: bool,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    pub fn new(r: R) -> ArmorReader<R> {
+        ArmorReader {
+            r,
+            offset: 0,
+            pending: None,
+            done: false,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.r
+    }
+
+    fn next_armor(&mut self) -> Result<Option<(u64, Armor)>> {
+        let begin = match self.pending.take() {
+            Some(begin) => Some(begin),
+            None => find_begin(&mut self.r, &mut self.offset)?,
+        };
+        let (start, block_type) = match begin {
+            Some(begin) => begin,
+            None => return Ok(None),
+        };
+        let armor = read_armor_body(&mut self.r, block_type, &mut self.offset);
+        if let Err(err) = &armor {
+            if let Some(begin) = err.downcast_ref::<UnexpectedBegin>() {
+                self.pending = Some((begin.offset, begin.block_type.clone()));
+            }
+        }
+        let armor = armor
+            .with_context(|| format!("Failed to read armor at offset {}", start))?;
+        Ok(Some((start, armor)))
+    }
+}
+
+impl<R: BufRead> Iterator for ArmorReader<R> {
+    type Item = Result<(u64, Armor)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let armor = self.next_armor();
+        match &armor {
+            Ok(Some(_)) => (),
+            Ok(None) => self.done = true,
+            Err(err) => {
+                // the stream is still positioned after the broken block
+                if err.downcast_ref::<std::io::Error>().is_some() {
+                    self.done = true;
+                }
+            },
+        }
+        armor.transpose()
+    }
 }
 
 #[cfg(test)]
@@ -43,7 +347,7 @@ mod tests {
     use std::io::BufReader;
 
     fn bytes() -> Vec<u8> {
-        base64::decode(&r#"mQENBFu6q90BCADgD7Q9aH5683yt7hzPktDkAUNAZJHwYhUNeyGK43frPyDRWQmqN+oXTfiYWLQN
+        base64::decode(r#"mQENBFu6q90BCADgD7Q9aH5683yt7hzPktDkAUNAZJHwYhUNeyGK43frPyDRWQmqN+oXTfiYWLQN
 +d7KNBTnF9uwyBdaLM7SH44lLNYo8W09mVM2eK+wt19uf5HYNgAE8la45QLo/ce9CQVe1a4oXNWq
 6l0FOY7M+wLe+G2wMwz8RXGgwd/qQp4/PB5YpUhxnAnzClxvwymrL6BQXsRcKSMSD5bIzIv95n10
 5CvW5Hql7JR9zgOR+gHqVOH8HBUcZxMumrTM6aKLgAhgM8Sn36gCFOfjlG1b1OFLZhUtgro/nnEO
@@ -148,4 +452,183 @@ vA==
         let bytes = bytes();
         assert_eq!(key, bytes);
     }
+
+    #[test]
+    fn calculate_crc24() {
+        assert_eq!(crc24(b""), 0xb704ce);
+        assert_eq!(encode_crc24(&bytes()), "kRIv");
+    }
+
+    #[test]
+    fn armor_key() {
+        let mut buf = Vec::new();
+        write_armored(&mut buf, &BlockType::PublicKey, &[
+            ("Version", "GnuPG v2"),
+            ("Foo", "bar"),
+        ], &bytes()).expect("write_armored");
+
+        let buf = String::from_utf8(buf).expect("invalid utf8");
+        assert_eq!(buf, include_str!("../data/hans_acker.asc"));
+    }
+
+    #[test]
+    fn armor_roundtrip() {
+        let mut buf = Vec::new();
+        write_armored(&mut buf, &BlockType::Signature, &[], b"\x88\x01\x02").expect("write_armored");
+        assert!(buf.starts_with(b"-----BEGIN PGP SIGNATURE-----\n\n"));
+
+        let data = read_armored(&mut BufReader::new(&buf[..])).expect("read_armored");
+        assert_eq!(data, b"\x88\x01\x02");
+    }
+
+    #[test]
+    fn verify_checksum() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let (data, checksum) = read_armored_lenient(&mut &key[..]).expect("read_armored_lenient");
+        assert_eq!(data, bytes());
+        assert_eq!(checksum, Checksum::Valid);
+        read_armored_strict(&mut &key[..]).expect("read_armored_strict");
+    }
+
+    #[test]
+    fn verify_checksum_missing() {
+        let key = String::from_utf8(include_bytes!("../data/hans_acker.asc").to_vec()).unwrap();
+        let key = key.replace("=kRIv\n", "");
+        let (_data, checksum) = read_armored_lenient(&mut key.as_bytes()).expect("read_armored_lenient");
+        assert_eq!(checksum, Checksum::Missing);
+        read_armored_strict(&mut key.as_bytes()).expect("read_armored_strict");
+    }
+
+    #[test]
+    fn verify_checksum_mismatch() {
+        let key = String::from_utf8(include_bytes!("../data/hans_acker.asc").to_vec()).unwrap();
+        // flip a bit in the payload
+        let key = key.replace("mQENBFu6q90B", "mQENBFu6q91B");
+        let (_data, checksum) = read_armored_lenient(&mut key.as_bytes()).expect("read_armored_lenient");
+        assert!(matches!(checksum, Checksum::Mismatch { expected: 0x91122f, .. }));
+
+        let err = read_armored_strict(&mut key.as_bytes()).unwrap_err();
+        assert!(err.to_string().starts_with("Armor checksum mismatch"));
+        // the non-strict api still decodes the payload
+        read_armored(&mut key.as_bytes()).expect("read_armored");
+    }
+
+    #[test]
+    fn verify_checksum_malformed() {
+        let key = String::from_utf8(include_bytes!("../data/hans_acker.asc").to_vec()).unwrap();
+        for line in &["=kR!v\n", "=kRIvkRIv\n"] {
+            let key = key.replace("=kRIv\n", line);
+            let (data, checksum) = read_armored_lenient(&mut key.as_bytes()).expect("read_armored_lenient");
+            assert_eq!(data, bytes());
+            assert_eq!(checksum, Checksum::Malformed);
+            read_armored(&mut key.as_bytes()).expect("read_armored");
+
+            let err = read_armored_strict(&mut key.as_bytes()).unwrap_err();
+            assert_eq!(err.to_string(), "Armor checksum is malformed");
+        }
+    }
+
+    #[test]
+    fn unarmor_block_type_and_headers() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let armor = read_armor(&mut &key[..]).expect("read_armor");
+        assert_eq!(armor, Armor {
+            block_type: BlockType::PublicKey,
+            headers: vec![
+                (String::from("Version"), String::from("GnuPG v2")),
+                (String::from("Foo"), String::from("bar")),
+            ],
+            data: bytes(),
+            checksum: Checksum::Valid,
+        });
+        assert_eq!(armor.header("Version"), Some("GnuPG v2"));
+        assert_eq!(armor.header("Comment"), None);
+    }
+
+    #[test]
+    fn unarmor_other_block_type() {
+        let mut buf = Vec::new();
+        let block_type = BlockType::Other(String::from("MESSAGE, PART 1/2"));
+        write_armored(&mut buf, &block_type, &[("Comment", "foo: bar")], b"ohai").expect("write_armored");
+
+        let armor = read_armor(&mut &buf[..]).expect("read_armor");
+        assert_eq!(armor.block_type, block_type);
+        assert_eq!(armor.header("Comment"), Some("foo: bar"));
+        assert_eq!(armor.data, b"ohai");
+    }
+
+    #[test]
+    fn unarmor_multiple_blocks() {
+        let mut buf = Vec::new();
+        buf.extend(b"some text before the first block\n");
+        let first = buf.len() as u64;
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"first").expect("write_armored");
+        let second = buf.len() as u64;
+        write_armored(&mut buf, &BlockType::Signature, &[], b"second").expect("write_armored");
+        buf.extend(b"\n");
+        let third = buf.len() as u64;
+        buf.extend(include_bytes!("../data/hans_acker.asc"));
+
+        let blocks = ArmorReader::new(&buf[..])
+            .map(|armor| {
+                let (offset, armor) = armor.expect("armor");
+                (offset, armor.block_type, armor.data)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(blocks, vec![
+            (first, BlockType::PublicKey, b"first".to_vec()),
+            (second, BlockType::Signature, b"second".to_vec()),
+            (third, BlockType::PublicKey, bytes()),
+        ]);
+    }
+
+    #[test]
+    fn unarmor_multiple_blocks_error() {
+        let mut buf = Vec::new();
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"first").expect("write_armored");
+        buf.extend(b"-----BEGIN PGP SIGNATURE-----\n\n!!!\n-----END PGP SIGNATURE-----\n");
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"third").expect("write_armored");
+
+        let mut blocks = ArmorReader::new(&buf[..]);
+        assert_eq!(blocks.next().unwrap().unwrap().1.data, b"first");
+        assert!(blocks.next().unwrap().is_err());
+        assert_eq!(blocks.next().unwrap().unwrap().1.data, b"third");
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn unarmor_multiple_blocks_truncated() {
+        let mut buf = Vec::new();
+        buf.extend(b"-----BEGIN PGP SIGNATURE-----\n\nZmlyc3Q=\n");
+        let second = buf.len() as u64;
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"second").expect("write_armored");
+
+        let mut blocks = ArmorReader::new(&buf[..]);
+        let err = blocks.next().unwrap().unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Unexpected BEGIN line inside of armor");
+        let (offset, armor) = blocks.next().unwrap().unwrap();
+        assert_eq!(offset, second);
+        assert_eq!(armor.block_type, BlockType::PublicKey);
+        assert_eq!(armor.data, b"second");
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn unarmor_multiple_blocks_io_error() {
+        let mut buf = Vec::new();
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"first").expect("write_armored");
+        buf.extend(b"-----BEGIN PGP SIGNATURE-----\n\n\xff\n-----END PGP SIGNATURE-----\n");
+        write_armored(&mut buf, &BlockType::PublicKey, &[], b"third").expect("write_armored");
+
+        let mut blocks = ArmorReader::new(&buf[..]);
+        assert!(blocks.next().unwrap().is_ok());
+        assert!(blocks.next().unwrap().is_err());
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn unarmor_no_armor() {
+        assert!(read_armor(&mut &b"foo\nbar\n"[..]).is_err());
+    }
 }
@@ -0,0 +1,421 @@
+use crate::errors::*;
+use crate::packet::Tag;
+use crate::pubkey::{self, PublicKey};
+use crate::signature::{self, Signature, SignatureType};
+use crate::userid::UserId;
+use serde::{Serialize, Deserialize};
+
+/// A primary key or subkey, with the raw packet body that is needed to
+/// calculate fingerprints and to verify signatures over the key.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CertKey {
+    /// Body of the public key packet, for secret keys only the public
+    /// portion is kept
+    pub body: Vec<u8>,
+    pub key: PublicKey,
+    /// Whether the key was read from a secret key packet
+    pub secret: bool,
+}
+
+impl CertKey {
+    fn parse(body: &[u8], secret: bool) -> Result<CertKey> {
+        let body = if secret {
+            pubkey::public_part(body)?
+        } else {
+            body
+        };
+        let key = pubkey::parse(body)?;
+        Ok(CertKey {
+            body: body.to_vec(),
+            key,
+            secret,
+        })
+    }
+
+    pub fn fingerprint(&self) -> Result<String> {
+        pubkey::fingerprint(&self.body)
+    }
+
+    pub fn keyid(&self) -> Result<String> {
+        pubkey::keyid(&self.body)
+    }
+}
+
+/// A user ID with its certifications and certification revocations
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CertUserId {
+    pub userid: UserId,
+    pub signatures: Vec<Signature>,
+}
+
+/// A user attribute with its certifications and certification revocations
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CertUserAttribute {
+    pub data: Vec<u8>,
+    pub signatures: Vec<Signature>,
+}
+
+/// A subkey with its binding signatures and subkey revocations
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CertSubkey {
+    pub key: CertKey,
+    pub signatures: Vec<Signature>,
+}
+
+/// A transferable public key
+// https://tools.ietf.org/html/rfc4880#section-11.1
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cert {
+    pub primary: CertKey,
+    /// Key revocation signatures
+    pub revocations: Vec<Signature>,
+    /// Direct key signatures
+    pub direct_signatures: Vec<Signature>,
+    pub userids: Vec<CertUserId>,
+    pub user_attributes: Vec<CertUserAttribute>,
+    pub subkeys: Vec<CertSubkey>,
+    /// Packets that didn't fit the grammar and have been skipped
+    pub violations: Vec<String>,
+}
+
+impl Cert {
+    /// Assemble a certificate from a sequence of packets
+    pub fn from_packets<I: IntoIterator<Item=(Tag, Vec<u8>)>>(packets: I) -> Result<Cert> {
+        let mut builder = CertBuilder::new();
+        for (tag, body) in packets {
+            builder.push(tag, &body)?;
+        }
+        builder.finish()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Position {
+    #[default]
+    Primary,
+    UserId,
+    UserAttribute,
+    Subkey,
+    /// The last component couldn't be parsed, signatures are ignored
+    Skipped,
+}
+
+impl Position {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Position::Primary => "primary key",
+            Position::UserId => "user id",
+            Position::UserAttribute => "user attribute",
+            Position::Subkey => "subkey",
+            Position::Skipped => "skipped packet",
+        }
+    }
+}
+
+/// Consumes the packets of a single certificate in the order they appear in
+/// the stream. Packets that violate the grammar are skipped and recorded in
+/// `Cert::violations`, an error is only returned if the stream doesn't
+/// start with a primary key.
+#[derive(Debug, Default)]
+pub struct CertBuilder {
+    cert: Option<Cert>,
+    position: Position,
+    /// Number of packets after the primary key
+    packets: usize,
+    max_packets: Option<usize>,
+}
+
+impl CertBuilder {
+    pub fn new() -> CertBuilder {
+        CertBuilder::default()
+    }
+
+    /// Limit the number of packets after the primary key, further packets
+    /// are skipped and recorded as a violation. This protects against
+    /// certificates that are flooded with signatures.
+    pub fn with_max_packets(mut self, max_packets: usize) -> CertBuilder {
+        self.max_packets = Some(max_packets);
+        self
+    }
+
+    /// Returns true if the builder already received a primary key
+    pub fn has_primary(&self) -> bool {
+        self.cert.is_some()
+    }
+
+    pub fn push(&mut self, tag: Tag, body: &[u8]) -> Result<()> {
+        let cert = match (&mut self.cert, tag) {
+            (None, Tag::PublicKey) | (None, Tag::SecretKey) => {
+                let primary = CertKey::parse(body, tag == Tag::SecretKey)
+                    .context("Failed to parse primary key")?;
+                self.cert = Some(Cert {
+                    primary,
+                    revocations: Vec::new(),
+                    direct_signatures: Vec::new(),
+                    userids: Vec::new(),
+                    user_attributes: Vec::new(),
+                    subkeys: Vec::new(),
+                    violations: Vec::new(),
+                });
+                self.position = Position::Primary;
+                return Ok(());
+            },
+            (None, tag) => bail!("Certificate must start with a primary key, found {:?}", tag),
+            (Some(_), Tag::PublicKey) | (Some(_), Tag::SecretKey) => {
+                bail!("Unexpected primary key, certificate is already started")
+            },
+            (Some(cert), _) => cert,
+        };
+
+        self.packets += 1;
+        if let Some(max_packets) = self.max_packets {
+            if self.packets > max_packets {
+                if self.packets == max_packets + 1 {
+                    cert.violations.push(format!("Certificate has more than {} packets, skipping the rest", max_packets));
+                }
+                return Ok(());
+            }
+        }
+
+        match tag {
+            Tag::UserID | Tag::UserAttribute => {
+                if !cert.subkeys.is_empty() {
+                    cert.violations.push(format!("{:?} packet after subkeys", tag));
+                }
+                if tag == Tag::UserID {
+                    cert.userids.push(CertUserId {
+                        userid: UserId::parse(body),
+                        signatures: Vec::new(),
+                    });
+                    self.position = Position::UserId;
+                } else {
+                    cert.user_attributes.push(CertUserAttribute {
+                        data: body.to_vec(),
+                        signatures: Vec::new(),
+                    });
+                    self.position = Position::UserAttribute;
+                }
+            },
+            Tag::PublicSubkey | Tag::SecretSubkey => {
+                match CertKey::parse(body, tag == Tag::SecretSubkey) {
+                    Ok(key) => {
+                        cert.subkeys.push(CertSubkey {
+                            key,
+                            signatures: Vec::new(),
+                        });
+                        self.position = Position::Subkey;
+                    },
+                    Err(err) => {
+                        cert.violations.push(format!("Failed to parse subkey: {:#}", err));
+                        self.position = Position::Skipped;
+                    },
+                }
+            },
+            Tag::Signature => {
+                let sig = match signature::parse(body) {
+                    Ok(sig) => sig,
+                    Err(err) => {
+                        cert.violations.push(format!("Failed to parse signature: {:#}", err));
+                        return Ok(());
+                    },
+                };
+                Self::push_signature(cert, self.position, sig);
+            },
+            // trust packets are implementation specific and only found in
+            // local keyrings, marker packets must be ignored
+            Tag::Trust | Tag::Marker => (),
+            _ => cert.violations.push(format!("Unexpected {:?} packet in certificate", tag)),
+        }
+
+        Ok(())
+    }
+
+    fn push_signature(cert: &mut Cert, position: Position, sig: Signature) {
+        let is_certification = matches!(sig.sigtype,
+            SignatureType::GenericCertification
+            | SignatureType::PersonaCertification
+            | SignatureType::CasualCertification
+            | SignatureType::PositiveCertification
+            | SignatureType::CertificationRevocation);
+
+        let signatures = match (position, sig.sigtype) {
+            (Position::Primary, SignatureType::KeyRevocation) => Some(&mut cert.revocations),
+            (Position::Primary, SignatureType::DirectKey) => Some(&mut cert.direct_signatures),
+            (Position::UserId, _) if is_certification => {
+                cert.userids.last_mut().map(|uid| &mut uid.signatures)
+            },
+            (Position::UserAttribute, _) if is_certification => {
+                cert.user_attributes.last_mut().map(|attr| &mut attr.signatures)
+            },
+            (Position::Subkey, SignatureType::SubkeyBinding)
+            | (Position::Subkey, SignatureType::SubkeyRevocation) => {
+                cert.subkeys.last_mut().map(|subkey| &mut subkey.signatures)
+            },
+            (Position::Skipped, _) => return,
+            _ => None,
+        };
+
+        if let Some(signatures) = signatures {
+            signatures.push(sig);
+        } else {
+            cert.violations.push(format!("Unexpected {:?} signature on {}", sig.sigtype, position.as_str()));
+        }
+    }
+
+    pub fn finish(self) -> Result<Cert> {
+        let mut cert = match self.cert {
+            Some(cert) => cert,
+            None => bail!("Certificate has no primary key"),
+        };
+
+        // v6 certificates may omit user ids
+        if cert.userids.is_empty() && cert.primary.key.version < 6 {
+            cert.violations.push(String::from("Certificate has no user id"));
+        }
+        for subkey in &cert.subkeys {
+            let has_binding = subkey.signatures.iter()
+                .any(|sig| sig.sigtype == SignatureType::SubkeyBinding);
+            if !has_binding {
+                let keyid = subkey.key.keyid().unwrap_or_default();
+                cert.violations.push(format!("Subkey {} has no binding signature", keyid));
+            }
+        }
+
+        Ok(cert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::packets;
+
+    #[test]
+    fn assemble_rsa_cert() {
+        let cert = Cert::from_packets(packets(include_bytes!("../data/hans_acker.asc"))).expect("from_packets");
+        assert_eq!(cert.primary.keyid().unwrap(), "334D08A1D19D963F");
+        assert!(!cert.primary.secret);
+        assert!(cert.revocations.is_empty());
+        assert!(cert.direct_signatures.is_empty());
+
+        assert_eq!(cert.userids.len(), 1);
+        assert_eq!(cert.userids[0].userid.raw, b"Hans Acker (example comment) <hans.acker@example.com>");
+        assert_eq!(cert.userids[0].userid.normalized_email().as_deref(), Some("hans.acker@example.com"));
+        assert_eq!(cert.userids[0].signatures.len(), 1);
+        assert_eq!(cert.userids[0].signatures[0].sigtype, SignatureType::PositiveCertification);
+
+        assert_eq!(cert.subkeys.len(), 1);
+        assert_eq!(cert.subkeys[0].key.keyid().unwrap(), "43E852DB7555FC0E");
+        assert_eq!(cert.subkeys[0].signatures.len(), 1);
+        assert_eq!(cert.subkeys[0].signatures[0].sigtype, SignatureType::SubkeyBinding);
+
+        assert!(cert.violations.is_empty(), "{:?}", cert.violations);
+    }
+
+    #[test]
+    fn assemble_dsa_cert() {
+        let packets = packets(include_bytes!("../data/freebsd.asc"));
+        // the file contains a second certificate
+        let end = packets.iter()
+            .rposition(|(tag, _)| *tag == Tag::PublicKey)
+            .unwrap();
+        assert_ne!(end, 0);
+
+        let cert = Cert::from_packets(packets.into_iter().take(end)).expect("from_packets");
+        assert_eq!(cert.primary.keyid().unwrap(), "15D68804CA6CDFB2");
+        assert_eq!(cert.subkeys.len(), 1);
+        assert_eq!(cert.subkeys[0].key.keyid().unwrap(), "E8C96EEFA3071809");
+        assert!(!cert.userids.is_empty());
+        assert!(cert.userids.iter().all(|uid| !uid.signatures.is_empty()));
+        assert!(cert.violations.is_empty(), "{:?}", cert.violations);
+    }
+
+    #[test]
+    fn secret_primary_key() {
+        let mut packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let public = packets[0].1.clone();
+        packets[0].0 = Tag::SecretKey;
+        packets[0].1.extend(&[0x00, 0x00, 0x08, 0xff, 0x00, 0x00]);
+
+        let cert = Cert::from_packets(packets).expect("from_packets");
+        assert!(cert.primary.secret);
+        assert_eq!(cert.primary.body, public);
+    }
+
+    #[test]
+    fn secret_subkey_unknown_algorithm() {
+        let mut packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let mut subkey = b"\x04\x63\x87\x7f\xe3\x1b".to_vec();
+        subkey.extend(&[0x42; 32]);
+        packets[3] = (Tag::SecretSubkey, subkey);
+
+        let cert = Cert::from_packets(packets).expect("from_packets");
+        // the secret key material must not end up in the certificate
+        assert!(cert.subkeys.is_empty());
+        assert_eq!(cert.violations.len(), 1);
+        assert!(cert.violations[0].starts_with("Failed to parse subkey: "));
+    }
+
+    #[test]
+    fn must_start_with_primary_key() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let err = Cert::from_packets(packets.into_iter().skip(1)).unwrap_err();
+        assert_eq!(err.to_string(), "Certificate must start with a primary key, found UserID");
+    }
+
+    #[test]
+    fn second_primary_key() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let primary = packets[0].clone();
+        assert!(Cert::from_packets(packets.into_iter().chain(Some(primary))).is_err());
+    }
+
+    #[test]
+    fn grammar_violations() {
+        let mut packets = packets(include_bytes!("../data/hans_acker.asc"));
+        // move the user id and its certification behind the subkey binding
+        let uid = packets.remove(1);
+        let cert = packets.remove(1);
+        packets.push(uid);
+        packets.push(cert);
+        // a binding signature right after the primary key
+        let binding = packets[2].clone();
+        packets.insert(1, binding);
+
+        let cert = Cert::from_packets(packets).expect("from_packets");
+        assert_eq!(cert.userids.len(), 1);
+        assert_eq!(cert.userids[0].signatures.len(), 1);
+        assert_eq!(cert.subkeys[0].signatures.len(), 1);
+        assert_eq!(cert.violations, vec![
+            String::from("Unexpected SubkeyBinding signature on primary key"),
+            String::from("UserID packet after subkeys"),
+        ]);
+    }
+
+    #[test]
+    fn max_packets() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let mut builder = CertBuilder::new().with_max_packets(2);
+        for (tag, body) in packets {
+            builder.push(tag, &body).expect("push");
+        }
+        let cert = builder.finish().expect("finish");
+        assert_eq!(cert.userids.len(), 1);
+        assert_eq!(cert.userids[0].signatures.len(), 1);
+        assert!(cert.subkeys.is_empty());
+        assert_eq!(cert.violations, vec![
+            String::from("Certificate has more than 2 packets, skipping the rest"),
+        ]);
+    }
+
+    #[test]
+    fn missing_userid_and_binding() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let packets = vec![packets[0].clone(), packets[3].clone()];
+        let cert = Cert::from_packets(packets).expect("from_packets");
+        assert_eq!(cert.violations, vec![
+            String::from("Certificate has no user id"),
+            String::from("Subkey 43E852DB7555FC0E has no binding signature"),
+        ]);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::armor::{self, BlockType};
+use crate::errors::*;
+use crate::packet::Tag;
+use crate::signature::{self, Signature};
+use crate::Parser;
+use std::io::prelude::*;
+
+// https://tools.ietf.org/html/rfc4880#section-7
+#[derive(Debug, PartialEq)]
+pub struct CleartextMessage {
+    /// Hash algorithms from the `Hash:` armor headers
+    pub hashes: Vec<String>,
+    /// The signed text with dash-escaping removed, trailing whitespace
+    /// stripped and `\r\n` line endings, as it's used for hashing
+    pub text: String,
+    pub signatures: Vec<Signature>,
+}
+
+fn read_line<R: BufRead>(r: &mut R, buf: &mut String) -> Result<bool> {
+    buf.clear();
+    let n = r.read_line(buf)?;
+    Ok(n > 0)
+}
+
+pub fn read_cleartext<R: BufRead>(r: &mut R) -> Result<CleartextMessage> {
+    let mut buf = String::new();
+
+    loop {
+        if !read_line(r, &mut buf)? {
+            bail!("Failed to find beginning of signed message");
+        }
+        if buf.trim_end() == "-----BEGIN PGP SIGNED MESSAGE-----" {
+            break;
+        }
+    }
+
+    let mut hashes = Vec::new();
+    loop {
+        if !read_line(r, &mut buf)? {
+            bail!("Unexpected end of signed message headers");
+        }
+
+        let tr = buf.trim_end();
+        if tr.is_empty() {
+            break;
+        } else if let Some(value) = tr.strip_prefix("Hash:") {
+            hashes.extend(value.split(',')
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty()));
+        } else {
+            debug!("Ignoring signed message header: {:?}", tr);
+        }
+    }
+
+    let mut lines = Vec::new();
+    loop {
+        if !read_line(r, &mut buf)? {
+            bail!("Failed to find signature of signed message");
+        }
+
+        let line = buf.trim_end_matches(['\r', '\n']);
+        if line == "-----BEGIN PGP SIGNATURE-----" {
+            break;
+        }
+
+        let line = line.strip_prefix("- ").unwrap_or(line);
+        lines.push(line.trim_end_matches([' ', '\t']).to_string());
+    }
+    let text = lines.join("\r\n");
+
+    let mut offset = 0;
+    let armor = armor::read_armor_body(r, BlockType::Signature, &mut offset)
+        .context("Failed to read signature armor")?;
+
+    let mut signatures = Vec::new();
+    let mut parser = Parser::new(armor.data.as_slice());
+    while let Some((tag, body)) = parser.next_packet()? {
+        if tag == Tag::Signature {
+            let sig = signature::parse(&body)?;
+            signatures.push(sig);
+        }
+    }
+
+    Ok(CleartextMessage {
+        hashes,
+        text,
+        signatures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::armor::{read_armored, write_armored};
+
+    fn signature_armor() -> Vec<u8> {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let key = read_armored(&mut &key[..]).expect("read_armored");
+
+        let mut parser = Parser::new(key.as_slice());
+        loop {
+            let start = parser.offset() as usize;
+            let (tag, _body) = parser.next_packet().unwrap().expect("no signature found");
+            if tag == Tag::Signature {
+                let end = parser.offset() as usize;
+                let mut buf = Vec::new();
+                write_armored(&mut buf, &BlockType::Signature, &[], &key[start..end]).unwrap();
+                return buf;
+            }
+        }
+    }
+
+    #[test]
+    fn parse_cleartext_signature() {
+        let mut msg = String::from("-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Origin: Debian
+Suite: stable
+- -----BEGIN PGP SIGNATURE-----
+- - foo
+-- bar
+
+");
+        msg += &String::from_utf8(signature_armor()).unwrap();
+
+        let msg = read_cleartext(&mut msg.as_bytes()).expect("read_cleartext");
+        assert_eq!(msg.hashes, vec![String::from("SHA256")]);
+        assert_eq!(msg.text, "Origin: Debian\r\nSuite: stable\r\n-----BEGIN PGP SIGNATURE-----\r\n- foo\r\n-- bar\r\n");
+        assert_eq!(msg.signatures.len(), 1);
+        assert_eq!(msg.signatures[0].keyid.as_deref(), Some("334D08A1D19D963F"));
+        assert_eq!(msg.signatures[0].fingerprint.as_deref(), Some("CB378ED5E1306C1D3785CA81334D08A1D19D963F"));
+    }
+
+    #[test]
+    fn parse_cleartext_multiple_hashes() {
+        let mut msg = String::from("-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA1, SHA256
+Hash: SHA512
+
+hello world
+");
+        msg += &String::from_utf8(signature_armor()).unwrap();
+
+        let msg = read_cleartext(&mut msg.as_bytes()).expect("read_cleartext");
+        assert_eq!(msg.hashes, vec![
+            String::from("SHA1"),
+            String::from("SHA256"),
+            String::from("SHA512"),
+        ]);
+        assert_eq!(msg.text, "hello world");
+        assert_eq!(msg.signatures.len(), 1);
+    }
+
+    #[test]
+    fn parse_cleartext_missing_signature() {
+        let msg = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+hello world
+";
+        assert!(read_cleartext(&mut msg.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use byteorder::ReadBytesExt;
+use crate::errors::*;
+use serde::{Serialize, Deserialize};
+use std::io::Read;
+
+/// Default limit for how much larger than the compressed body the
+/// decompressed data may be, to reject zip bombs.
+pub const DEFAULT_MAX_RATIO: usize = 1000;
+
+// https://tools.ietf.org/html/rfc4880#section-9.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    Uncompressed,
+    Zip,
+    Zlib,
+    Bzip2,
+    Unknown(u8),
+}
+
+impl CompressionAlgorithm {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            0 => CompressionAlgorithm::Uncompressed,
+            1 => CompressionAlgorithm::Zip,
+            2 => CompressionAlgorithm::Zlib,
+            3 => CompressionAlgorithm::Bzip2,
+            b => CompressionAlgorithm::Unknown(b),
+        }
+    }
+}
+
+/// Split the body of a compressed data packet into the algorithm and the
+/// compressed data.
+// https://tools.ietf.org/html/rfc4880#section-5.6
+pub fn parse_header(mut body: &[u8]) -> Result<(CompressionAlgorithm, &[u8])> {
+    let algorithm = CompressionAlgorithm::from_byte(body.read_u8()?);
+    Ok((algorithm, body))
+}
+
+fn read_limited<R: Read>(r: R, limit: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(limit as u64 + 1).read_to_end(&mut buf)?;
+    if buf.len() > limit {
+        bail!("Decompressed data exceeds limit of {} bytes", limit);
+    }
+    Ok(buf)
+}
+
+/// Decompress the data of a compressed data packet. Fails if the output
+/// would be larger than `limit`, or if support for the algorithm isn't
+/// enabled with the `zip`, `zlib` or `bzip2` features.
+pub fn decompress(algorithm: CompressionAlgorithm, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    debug!("Decompressing {} bytes with {:?}", data.len(), algorithm);
+    match algorithm {
+        CompressionAlgorithm::Uncompressed => read_limited(data, limit),
+        #[cfg(feature = "zip")]
+        CompressionAlgorithm::Zip => read_limited(flate2::read::DeflateDecoder::new(data), limit),
+        #[cfg(feature = "zlib")]
+        CompressionAlgorithm::Zlib => read_limited(flate2::read::ZlibDecoder::new(data), limit),
+        #[cfg(feature = "bzip2")]
+        CompressionAlgorithm::Bzip2 => read_limited(bzip2::read::BzDecoder::new(data), limit),
+        algorithm => bail!("Unsupported compression algorithm: {:?}", algorithm),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uncompressed() {
+        let (algorithm, data) = parse_header(b"\x00hello world").unwrap();
+        assert_eq!(algorithm, CompressionAlgorithm::Uncompressed);
+        assert_eq!(decompress(algorithm, data, 11).unwrap(), b"hello world");
+        assert!(decompress(algorithm, data, 10).is_err());
+    }
+
+    #[test]
+    fn unknown_algorithm() {
+        let (algorithm, data) = parse_header(b"\x6ehello world").unwrap();
+        assert_eq!(algorithm, CompressionAlgorithm::Unknown(110));
+        assert!(decompress(algorithm, data, 1024).is_err());
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn decompress_zlib_bomb() {
+        use flate2::{Compression, write::ZlibEncoder};
+        use std::io::Write;
+
+        let mut w = ZlibEncoder::new(Vec::new(), Compression::best());
+        w.write_all(&[0; 1024 * 1024]).unwrap();
+        let data = w.finish().unwrap();
+
+        assert_eq!(decompress(CompressionAlgorithm::Zlib, &data, 1024 * 1024).unwrap().len(), 1024 * 1024);
+        let err = decompress(CompressionAlgorithm::Zlib, &data, 4096).unwrap_err();
+        assert_eq!(err.to_string(), "Decompressed data exceeds limit of 4096 bytes");
+    }
+}
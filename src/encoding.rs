@@ -1,5 +1,7 @@
+use crate::algorithm::HashAlgorithm;
 use crate::errors::*;
 use byteorder::{BigEndian, ReadBytesExt};
+use serde::{Serialize, Deserialize};
 use std::io::Read;
 
 pub trait ReadValue<'a> {
@@ -45,3 +47,232 @@ pub fn read_length<R: Read>(l0: usize, s: &mut R) -> Result<usize> {
         s.read_u32::<BigEndian>()? as usize
     })
 }
+
+// https://tools.ietf.org/html/rfc4880#section-5.2.3.1
+pub fn read_subpacket_length<R: Read>(l0: usize, s: &mut R) -> Result<usize> {
+    Ok(if l0 < 192 {
+        l0
+    } else if l0 < 255 {
+        let l1 = s.read_u8()? as usize;
+        ((l0 - 192) << 8) + l1 + 192
+    } else {
+        s.read_u32::<BigEndian>()? as usize
+    })
+}
+
+/// GnuPG extensions for secret keys that aren't stored in the packet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GnuS2k {
+    /// The secret key material is missing, e.g. after
+    /// `--export-secret-subkeys`
+    Dummy,
+    /// The secret key is stored on a smartcard
+    DivertToCard {
+        serial: Vec<u8>,
+    },
+    Unknown(u8),
+}
+
+/// String-to-key specifier, describes how a passphrase is turned into a
+/// symmetric key
+// https://tools.ietf.org/html/rfc4880#section-3.7.1
+// https://datatracker.ietf.org/doc/html/rfc9580#section-3.7.1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum S2k {
+    Simple {
+        hash: HashAlgorithm,
+    },
+    Salted {
+        hash: HashAlgorithm,
+        salt: [u8; 8],
+    },
+    IteratedSalted {
+        hash: HashAlgorithm,
+        salt: [u8; 8],
+        /// Coded iteration count, see `iterations`
+        count: u8,
+    },
+    Argon2 {
+        salt: [u8; 16],
+        /// Number of passes
+        passes: u8,
+        /// Degree of parallelism
+        parallelism: u8,
+        /// Memory size as exponent, see `memory_kib`
+        encoded_memory: u8,
+    },
+    /// GnuPG extension 101
+    Gnu {
+        hash: HashAlgorithm,
+        mode: GnuS2k,
+    },
+}
+
+impl S2k {
+    pub fn read(body: &mut &[u8]) -> Result<S2k> {
+        let kind = body.read_u8()?;
+        Ok(match kind {
+            0 => S2k::Simple {
+                hash: HashAlgorithm::from_byte(body.read_u8()?),
+            },
+            1 => {
+                let hash = HashAlgorithm::from_byte(body.read_u8()?);
+                let mut salt = [0; 8];
+                body.read_exact(&mut salt)?;
+                S2k::Salted {
+                    hash,
+                    salt,
+                }
+            },
+            3 => {
+                let hash = HashAlgorithm::from_byte(body.read_u8()?);
+                let mut salt = [0; 8];
+                body.read_exact(&mut salt)?;
+                let count = body.read_u8()?;
+                S2k::IteratedSalted {
+                    hash,
+                    salt,
+                    count,
+                }
+            },
+            4 => {
+                let mut salt = [0; 16];
+                body.read_exact(&mut salt)?;
+                S2k::Argon2 {
+                    salt,
+                    passes: body.read_u8()?,
+                    parallelism: body.read_u8()?,
+                    encoded_memory: body.read_u8()?,
+                }
+            },
+            101 => {
+                let hash = HashAlgorithm::from_byte(body.read_u8()?);
+                let mut magic = [0; 3];
+                body.read_exact(&mut magic)?;
+                if &magic != b"GNU" {
+                    bail!("unsupported s2k extension: {:?}", magic);
+                }
+                let mode = match body.read_u8()? {
+                    1 => GnuS2k::Dummy,
+                    2 => {
+                        let len = body.read_u8()? as usize;
+                        if len > 16 {
+                            bail!("card serial number is too long");
+                        }
+                        let mut serial = vec![0; len];
+                        body.read_exact(&mut serial)?;
+                        GnuS2k::DivertToCard {
+                            serial,
+                        }
+                    },
+                    mode => GnuS2k::Unknown(mode),
+                };
+                S2k::Gnu {
+                    hash,
+                    mode,
+                }
+            },
+            _ => bail!("unsupported s2k specifier: {}", kind),
+        })
+    }
+
+    /// Number of bytes that are hashed for iterated and salted s2k
+    // https://tools.ietf.org/html/rfc4880#section-3.7.1.3
+    pub fn iterations(&self) -> Option<u32> {
+        match self {
+            S2k::IteratedSalted { count, .. } => {
+                Some((16 + (*count as u32 & 15)) << ((*count as u32 >> 4) + 6))
+            },
+            _ => None,
+        }
+    }
+
+    /// Memory size used by argon2 in KiB, `None` if the exponent is too large
+    // https://datatracker.ietf.org/doc/html/rfc9580#section-3.7.1.4
+    pub fn memory_kib(&self) -> Option<u64> {
+        match self {
+            S2k::Argon2 { encoded_memory, .. } => 1u64.checked_shl(*encoded_memory as u32),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the secret key isn't actually protected by this s2k
+    /// because it's missing or stored elsewhere
+    pub fn is_gnu_extension(&self) -> bool {
+        matches!(self, S2k::Gnu { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_s2k() {
+        let mut body = &b"\x03\x02\x01\x02\x03\x04\x05\x06\x07\x08\xffrest"[..];
+        let s2k = S2k::read(&mut body).expect("read");
+        assert_eq!(s2k, S2k::IteratedSalted {
+            hash: HashAlgorithm::Sha1,
+            salt: [1, 2, 3, 4, 5, 6, 7, 8],
+            count: 0xff,
+        });
+        assert_eq!(body, b"rest");
+
+        let mut body = &b"\x00\x08"[..];
+        assert_eq!(S2k::read(&mut body).unwrap(), S2k::Simple { hash: HashAlgorithm::Sha256 });
+        let mut body = &b"\x01\x08\x01\x02"[..];
+        assert!(S2k::read(&mut body).is_err());
+        let mut body = &b"\x02\x08"[..];
+        assert!(S2k::read(&mut body).is_err());
+    }
+
+    #[test]
+    fn s2k_iterations() {
+        let s2k = |count| S2k::IteratedSalted {
+            hash: HashAlgorithm::Sha256,
+            salt: [0; 8],
+            count,
+        };
+        assert_eq!(s2k(0x00).iterations(), Some(1024));
+        assert_eq!(s2k(0x60).iterations(), Some(65536));
+        assert_eq!(s2k(0xff).iterations(), Some(65011712));
+        assert_eq!(S2k::Simple { hash: HashAlgorithm::Sha1 }.iterations(), None);
+    }
+
+    #[test]
+    fn read_argon2_s2k() {
+        let mut body = &hex::decode("04a3be1a6d3ea5a9b8bb5c6d2cd4c901af010415").unwrap()[..];
+        let s2k = S2k::read(&mut body).expect("read");
+        assert_eq!(s2k, S2k::Argon2 {
+            salt: [0xa3, 0xbe, 0x1a, 0x6d, 0x3e, 0xa5, 0xa9, 0xb8, 0xbb, 0x5c, 0x6d, 0x2c, 0xd4, 0xc9, 0x01, 0xaf],
+            passes: 1,
+            parallelism: 4,
+            encoded_memory: 21,
+        });
+        assert_eq!(s2k.memory_kib(), Some(2 * 1024 * 1024));
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn read_gnu_s2k() {
+        let mut body = &b"\x65\x00GNU\x01"[..];
+        let s2k = S2k::read(&mut body).expect("read");
+        assert_eq!(s2k, S2k::Gnu {
+            hash: HashAlgorithm::Unknown(0),
+            mode: GnuS2k::Dummy,
+        });
+        assert!(s2k.is_gnu_extension());
+
+        let mut body = &b"\x65\x00GNU\x02\x04\xd2\x76\x00\x01"[..];
+        let s2k = S2k::read(&mut body).expect("read");
+        assert_eq!(s2k, S2k::Gnu {
+            hash: HashAlgorithm::Unknown(0),
+            mode: GnuS2k::DivertToCard {
+                serial: vec![0xd2, 0x76, 0x00, 0x01],
+            },
+        });
+
+        let mut body = &b"\x65\x00FOO\x01"[..];
+        assert!(S2k::read(&mut body).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::cert::{Cert, CertBuilder};
+use crate::errors::*;
+use crate::packet::{Tag, UnknownTag};
+use crate::Parser;
+use std::io::Read;
+
+/// Splits a stream of concatenated certificates, like a `pubring.gpg` or a
+/// keyserver dump, and yields one certificate at a time. Only the packets of
+/// the current certificate are kept in memory.
+///
+/// If a certificate fails to parse, the error is returned and the following
+/// packets are skipped until the next primary key. Packets with an unknown
+/// tag, like padding packets, are skipped. Other errors while reading the
+/// packet stream itself are fatal and end the iteration.
+pub struct KeyringParser<R: Read> {
+    parser: Parser<R>,
+    next_primary: Option<(u64, Tag, Vec<u8>)>,
+    max_packets: Option<usize>,
+    done: bool,
+}
+
+fn is_primary(tag: Tag) -> bool {
+    tag == Tag::PublicKey || tag == Tag::SecretKey
+}
+
+impl<R: Read> KeyringParser<R> {
+    pub fn new(r: R) -> KeyringParser<R> {
+        KeyringParser::from_parser(Parser::new(r))
+    }
+
+    pub fn with_max_alloc(r: R, max_alloc: usize) -> KeyringParser<R> {
+        KeyringParser::from_parser(Parser::with_max_alloc(r, max_alloc))
+    }
+
+    pub fn from_parser(parser: Parser<R>) -> KeyringParser<R> {
+        KeyringParser {
+            parser,
+            next_primary: None,
+            max_packets: None,
+            done: false,
+        }
+    }
+
+    /// Limit the number of packets of a certificate after its primary key,
+    /// see `CertBuilder::with_max_packets`
+    pub fn with_max_packets(mut self, max_packets: usize) -> KeyringParser<R> {
+        self.max_packets = Some(max_packets);
+        self
+    }
+
+    /// Number of bytes consumed from the reader so far
+    pub fn offset(&self) -> u64 {
+        self.parser.offset()
+    }
+
+    fn read_packet(&mut self) -> Result<Option<(u64, Tag, Vec<u8>)>> {
+        loop {
+            let offset = self.parser.offset();
+            match self.parser.next_packet() {
+                Ok(packet) => return Ok(packet.map(|(tag, body)| (offset, tag, body))),
+                Err(err) => match err.downcast_ref::<UnknownTag>() {
+                    Some(UnknownTag(tag)) => debug!("Skipping packet with unknown tag {} at offset {}", tag, offset),
+                    None => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Read the next certificate, returns `Ok(None)` at the end of the stream
+    pub fn next_cert(&mut self) -> Result<Option<Cert>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let (start, tag, body) = match self.next_primary.take() {
+            Some(packet) => packet,
+            None => match self.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.done = true;
+                    return Ok(None);
+                },
+                Err(err) => {
+                    self.done = true;
+                    return Err(err);
+                },
+            },
+        };
+
+        let mut builder = CertBuilder::new();
+        if let Some(max_packets) = self.max_packets {
+            builder = builder.with_max_packets(max_packets);
+        }
+        let mut result = builder.push(tag, &body);
+        loop {
+            match self.read_packet() {
+                Ok(Some((offset, tag, body))) => {
+                    if is_primary(tag) {
+                        self.next_primary = Some((offset, tag, body));
+                        break;
+                    }
+                    if result.is_ok() {
+                        result = builder.push(tag, &body);
+                    }
+                },
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                },
+                Err(err) => {
+                    self.done = true;
+                    return Err(err);
+                },
+            }
+        }
+
+        result.and_then(|_| builder.finish())
+            .with_context(|| format!("Failed to parse certificate at offset {}", start))
+            .map(Some)
+    }
+}
+
+impl<R: Read> Iterator for KeyringParser<R> {
+    type Item = Result<Cert>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_cert().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::armor::read_armored;
+
+    fn keyring(armored: &[&[u8]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for armored in armored {
+            buf.extend(read_armored(&mut &armored[..]).expect("read_armored"));
+        }
+        buf
+    }
+
+    fn keyids(certs: &[Cert]) -> Vec<String> {
+        certs.iter()
+            .map(|cert| cert.primary.keyid().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn split_keyring() {
+        let buf = keyring(&[
+            include_bytes!("../data/hans_acker.asc"),
+            include_bytes!("../data/freebsd.asc"),
+        ]);
+
+        let certs = KeyringParser::new(buf.as_slice())
+            .collect::<Result<Vec<_>>>()
+            .expect("collect");
+        assert_eq!(keyids(&certs), vec![
+            "334D08A1D19D963F",
+            "15D68804CA6CDFB2",
+            "ED67ECD65DCF6AE7",
+        ]);
+        assert_eq!(certs[2].subkeys[0].key.keyid().unwrap(), "B64357A343D9CBAE");
+    }
+
+    #[test]
+    fn max_packets_per_cert() {
+        let buf = keyring(&[
+            include_bytes!("../data/hans_acker.asc"),
+            include_bytes!("../data/hans_acker.asc"),
+        ]);
+
+        let certs = KeyringParser::new(buf.as_slice())
+            .with_max_packets(2)
+            .collect::<Result<Vec<_>>>()
+            .expect("collect");
+        assert_eq!(keyids(&certs), vec![
+            "334D08A1D19D963F",
+            "334D08A1D19D963F",
+        ]);
+        for cert in &certs {
+            assert!(cert.subkeys.is_empty());
+            assert_eq!(cert.violations, vec![
+                String::from("Certificate has more than 2 packets, skipping the rest"),
+            ]);
+        }
+    }
+
+    #[test]
+    fn empty_keyring() {
+        let mut parser = KeyringParser::new(&[][..]);
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn skip_broken_cert() {
+        let mut buf = keyring(&[include_bytes!("../data/hans_acker.asc")]);
+        // a stray user id packet before the first primary key
+        let mut broken = vec![0xb4, 0x03];
+        broken.extend(b"foo");
+        broken.extend(&buf);
+        buf = broken.clone();
+        buf.extend(&broken[5..]);
+
+        let mut parser = KeyringParser::new(buf.as_slice());
+        let err = parser.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse certificate at offset 0");
+        let cert = parser.next().unwrap().expect("cert");
+        assert_eq!(cert.primary.keyid().unwrap(), "334D08A1D19D963F");
+        let cert = parser.next().unwrap().expect("cert");
+        assert_eq!(cert.primary.keyid().unwrap(), "334D08A1D19D963F");
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn skip_unknown_tags() {
+        let hans = keyring(&[include_bytes!("../data/hans_acker.asc")]);
+        let mut buf = hans.clone();
+        // padding packet
+        buf.extend(b"\xd5\x03\x00\x00\x00");
+        // private or experimental packet
+        buf.extend(b"\xfc\x02ab");
+        buf.extend(&hans);
+
+        let certs = KeyringParser::new(buf.as_slice())
+            .collect::<Result<Vec<_>>>()
+            .expect("collect");
+        assert_eq!(keyids(&certs), vec![
+            "334D08A1D19D963F",
+            "334D08A1D19D963F",
+        ]);
+        assert_eq!(certs[0].subkeys.len(), 1);
+    }
+
+    #[test]
+    fn truncated_keyring() {
+        let buf = keyring(&[
+            include_bytes!("../data/hans_acker.asc"),
+            include_bytes!("../data/hans_acker.asc"),
+        ]);
+        let buf = &buf[..buf.len() - 10];
+
+        let mut parser = KeyringParser::new(buf);
+        assert!(parser.next().unwrap().is_ok());
+        assert!(parser.next().unwrap().is_err());
+        assert!(parser.next().is_none());
+    }
+}
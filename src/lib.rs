@@ -1,19 +1,56 @@
 use std::io::prelude::*;
 use crate::errors::*;
 
+pub mod algorithm;
 pub mod armor;
+pub mod cert;
+pub use cert::Cert;
+pub mod cleartext;
+pub mod compressed;
 pub mod errors;
 mod encoding;
+pub use encoding::{GnuS2k, S2k};
+pub mod keyring;
+pub mod literal;
+pub mod message;
+pub use message::Message;
+pub mod onepass;
 pub mod packet;
-pub use packet::Tag;
+pub use packet::{Packet, Tag};
 pub mod pubkey;
+pub mod seckey;
+pub mod session_key;
 pub mod signature;
 pub use signature::Signature;
+#[cfg(test)]
+mod test_util;
+pub mod userid;
+pub use userid::UserId;
+pub mod verify;
+pub use verify::Verifier;
+
 
+/// Nested compressed data packets are rejected beyond this depth
+const MAX_COMPRESSION_DEPTH: usize = 4;
 
 pub struct Parser<R: Read> {
     r: R,
     max_alloc: Option<usize>,
+    offset: u64,
+    max_ratio: Option<usize>,
+    nested: Vec<Decompressed>,
+    /// Bytes that may still be decompressed for the current outermost
+    /// compressed data packet
+    budget: usize,
+}
+
+/// The decompressed content of a compressed data packet
+struct Decompressed {
+    /// Offset of the compressed data packet
+    offset: u64,
+    /// Number of compressed data packets this one is nested in, plus one
+    depth: usize,
+    data: std::io::Cursor<Vec<u8>>,
 }
 
 impl<R: Read> Parser<R> {
@@ -21,6 +58,10 @@ impl<R: Read> Parser<R> {
         Parser {
             r,
             max_alloc: None,
+            offset: 0,
+            max_ratio: None,
+            nested: Vec::new(),
+            budget: 0,
         }
     }
 
@@ -28,26 +69,161 @@ impl<R: Read> Parser<R> {
         Parser {
             r,
             max_alloc: Some(max_alloc),
+            offset: 0,
+            max_ratio: None,
+            nested: Vec::new(),
+            budget: 0,
         }
     }
 
+    /// Transparently decompress compressed data packets and return the
+    /// packets inside of them instead. The decompressed data of all nested
+    /// layers together may be at most `max_ratio` times larger than the
+    /// outermost compressed packet and is also bound by `max_alloc`.
+    /// Algorithms other than uncompressed need the
+    /// `zip`, `zlib` or `bzip2` features.
+    pub fn with_decompression(mut self, max_ratio: usize) -> Parser<R> {
+        self.max_ratio = Some(max_ratio);
+        self
+    }
+
     pub fn inner(&self) -> &R {
         &self.r
     }
+
+    /// Number of bytes consumed from the reader so far.
+    ///
+    /// Before calling `next_packet` this is the offset of the packet that is
+    /// about to be read, unless the parser is inside of decompressed data.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Read the next packet, distinguishing a clean end of stream (`Ok(None)`)
+    /// from malformed or truncated input (`Err`). Errors include the offset
+    /// of the packet that failed to parse.
+    pub fn next_packet(&mut self) -> Result<Option<(Tag, Vec<u8>)>> {
+        loop {
+            let (start, tag, body) = match self.nested.last_mut() {
+                Some(nested) => {
+                    let start = nested.data.position();
+                    let mut body = Vec::new();
+                    let tag = packet::read_next(&mut nested.data, &mut body, &self.max_alloc)
+                        .with_context(|| format!("Failed to read packet at offset {} in compressed data at offset {}",
+                                                 start, nested.offset))?;
+                    match tag {
+                        Some(tag) => (start, tag, body),
+                        None => {
+                            self.nested.pop();
+                            continue;
+                        },
+                    }
+                },
+                None => {
+                    let start = self.offset;
+                    match self.read_packet()? {
+                        Some((tag, body)) => (start, tag, body),
+                        None => return Ok(None),
+                    }
+                },
+            };
+
+            match (tag, self.max_ratio) {
+                (Tag::CompressedData, Some(max_ratio)) => self.push_compressed(start, &body, max_ratio)?,
+                _ => return Ok(Some((tag, body))),
+            }
+        }
+    }
+
+    fn push_compressed(&mut self, offset: u64, body: &[u8], max_ratio: usize) -> Result<()> {
+        let depth = match self.nested.last() {
+            Some(parent) => parent.depth + 1,
+            None => {
+                self.budget = body.len().saturating_mul(max_ratio);
+                if let Some(max_alloc) = self.max_alloc {
+                    self.budget = self.budget.min(max_alloc);
+                }
+                1
+            },
+        };
+        if depth > MAX_COMPRESSION_DEPTH {
+            bail!("Compressed data at offset {} is nested too deeply", offset);
+        }
+
+        // the parent isn't needed anymore if this was its last packet
+        if let Some(parent) = self.nested.last() {
+            if parent.data.position() >= parent.data.get_ref().len() as u64 {
+                self.nested.pop();
+            }
+        }
+
+        let (algorithm, data) = compressed::parse_header(body)?;
+        let data = compressed::decompress(algorithm, data, self.budget)
+            .with_context(|| format!("Failed to decompress packet at offset {}", offset))?;
+        self.budget -= data.len();
+
+        self.nested.push(Decompressed {
+            offset,
+            depth,
+            data: std::io::Cursor::new(data),
+        });
+        Ok(())
+    }
+
+    fn read_packet(&mut self) -> Result<Option<(Tag, Vec<u8>)>> {
+        let start = self.offset;
+        let mut packet_body = Vec::new();
+        let mut r = CountingReader {
+            inner: &mut self.r,
+            count: 0,
+        };
+        let tag = packet::read_next(&mut r, &mut packet_body, &self.max_alloc);
+        self.offset += r.count;
+        debug!("Received tag: {:?}", tag);
+
+        let tag = tag
+            .with_context(|| format!("Failed to read packet at offset {}", start))?;
+        Ok(tag.map(|tag| (tag, packet_body)))
+    }
+
+    /// Like `next_packet`, but parse the packet body according to its tag.
+    /// Packets that can't be parsed, e.g. keys of an unsupported version,
+    /// are returned as `Packet::Unparsed`.
+    pub fn next_typed(&mut self) -> Result<Option<Packet>> {
+        let start = self.offset;
+        let (tag, body) = match self.next_packet()? {
+            Some(packet) => packet,
+            None => return Ok(None),
+        };
+        let packet = match Packet::parse(tag, &body) {
+            Ok(packet) => packet,
+            Err(err) => {
+                debug!("Failed to parse {:?} packet at offset {}: {:#}", tag, start, err);
+                Packet::Unparsed(tag, body)
+            },
+        };
+        Ok(Some(packet))
+    }
 }
 
 impl<R: Read> Iterator for Parser<R> {
     type Item = (Tag, Vec<u8>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut packet_body = Vec::new();
-        let tag = packet::read(&mut self.r, &mut packet_body, &self.max_alloc);
-        debug!("Received tag: {:?}", tag);
+        self.next_packet().unwrap_or_default()
+    }
+}
 
-        match tag {
-            Ok(tag) => Some((tag, packet_body)),
-            _ => None,
-        }
+struct CountingReader<'a, R: Read> {
+    inner: &'a mut R,
+    count: u64,
+}
+
+impl<'a, R: Read> Read for CountingReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
     }
 }
 
@@ -117,8 +293,274 @@ mod tests {
         let key = include_bytes!("../data/freebsd.asc");
         let key = read_armored(&mut BufReader::new(&key[..])).expect("read_armored");
 
-        for (_tag, _body) in Parser::with_max_alloc(key.as_slice(), 3) {
-            unreachable!("max alloc didn't work");
+        let mut parser = Parser::with_max_alloc(key.as_slice(), 3);
+        assert!(parser.next().is_none(), "max alloc didn't work");
+    }
+
+    #[test]
+    fn next_packet_clean_eof() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let key = read_armored(&mut BufReader::new(&key[..])).expect("read_armored");
+
+        let mut parser = Parser::new(key.as_slice());
+        let mut n = 0;
+        while let Some((_tag, _body)) = parser.next_packet().expect("next_packet") {
+            n += 1;
+        }
+        assert_eq!(n, 5);
+        assert_eq!(parser.offset(), key.len() as u64);
+    }
+
+    #[test]
+    fn next_typed() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let key = read_armored(&mut BufReader::new(&key[..])).expect("read_armored");
+
+        let mut parser = Parser::new(key.as_slice());
+        let mut packets = Vec::new();
+        while let Some(packet) = parser.next_typed().expect("next_typed") {
+            packets.push(packet);
+        }
+
+        let tags = packets.iter().map(|p| p.tag()).collect::<Vec<_>>();
+        assert_eq!(tags, vec![
+            Tag::PublicKey,
+            Tag::UserID,
+            Tag::Signature,
+            Tag::PublicSubkey,
+            Tag::Signature,
+        ]);
+        assert_eq!(packets[1], Packet::UserID(UserId::parse(b"Hans Acker (example comment) <hans.acker@example.com>")));
+        if let Packet::Signature(sig) = &packets[2] {
+            assert_eq!(sig.keyid.as_deref(), Some("334D08A1D19D963F"));
+        } else {
+            panic!("expected signature packet");
+        }
+    }
+
+    #[test]
+    fn next_typed_literal_data() {
+        let msg = include_bytes!("../data/signed_message.asc");
+        let msg = read_armored(&mut BufReader::new(&msg[..])).expect("read_armored");
+
+        let mut parser = Parser::new(msg.as_slice());
+        let literal = loop {
+            match parser.next_typed().expect("next_typed") {
+                Some(Packet::LiteralData(literal)) => break literal,
+                Some(_) => (),
+                None => panic!("no literal data packet found"),
+            }
+        };
+        assert_eq!(literal.header.format, literal::DataFormat::Binary);
+        assert_eq!(literal.header.filename_lossy(), "hello.txt");
+        assert_eq!(literal.header.date, 1792320496);
+        assert_eq!(literal.data, b"hello world\n");
+    }
+
+    #[test]
+    fn next_typed_compressed_data() {
+        let msg = include_bytes!("../data/signed_zlib.asc");
+        let msg = read_armored(&mut BufReader::new(&msg[..])).expect("read_armored");
+
+        let mut parser = Parser::new(msg.as_slice());
+        match parser.next_typed().expect("next_typed") {
+            Some(Packet::CompressedData(algorithm, data)) => {
+                assert_eq!(algorithm, compressed::CompressionAlgorithm::Zlib);
+                assert!(!data.is_empty() && data.len() < msg.len());
+            },
+            packet => panic!("expected compressed data packet: {:?}", packet),
+        }
+        assert_eq!(parser.next_typed().expect("next_typed"), None);
+    }
+
+    #[test]
+    fn next_typed_unparsed() {
+        // a v7 public key, followed by a marker packet
+        let data = b"\x98\x06\x07\x63\x87\x7f\xe3\x1b\xa8\x03PGP";
+        let mut parser = Parser::new(&data[..]);
+        assert_eq!(parser.next_typed().expect("next_typed"),
+            Some(Packet::Unparsed(Tag::PublicKey, b"\x07\x63\x87\x7f\xe3\x1b".to_vec())));
+        assert_eq!(parser.next_typed().expect("next_typed"), Some(Packet::Marker));
+        assert_eq!(parser.next_typed().expect("next_typed"), None);
+    }
+
+    #[test]
+    fn parse_raw_packets() {
+        assert_eq!(Packet::parse(Tag::Marker, b"PGP").unwrap(), Packet::Marker);
+        assert_eq!(Packet::parse(Tag::Trust, &[0x00, 0x01]).unwrap(), Packet::Trust(vec![0x00, 0x01]));
+        assert_eq!(Packet::parse(Tag::SymIntData, &[0x01, 0xaa, 0xbb]).unwrap(),
+            Packet::SymIntData(1, vec![0xaa, 0xbb]));
+        assert_eq!(Packet::parse(Tag::ModificationDetectionCode, &[0x42; 20]).unwrap(),
+            Packet::ModificationDetectionCode([0x42; 20]));
+        assert!(Packet::parse(Tag::ModificationDetectionCode, &[0x42; 19]).is_err());
+        assert!(Packet::parse(Tag::SymIntData, &[]).is_err());
+
+        let packet = Packet::parse(Tag::UserAttribute, &[0x01, 0x02]).unwrap();
+        assert_eq!(packet.tag(), Tag::UserAttribute);
+    }
+
+    #[test]
+    fn next_packet_truncated() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let key = read_armored(&mut BufReader::new(&key[..])).expect("read_armored");
+        let key = &key[..key.len() - 10];
+
+        let mut parser = Parser::new(key);
+        let (offset, err) = loop {
+            let offset = parser.offset();
+            match parser.next_packet() {
+                Ok(Some(_)) => (),
+                Ok(None) => panic!("truncated packet wasn't detected"),
+                Err(err) => break (offset, err),
+            }
+        };
+        assert_eq!(offset, 936);
+        assert_eq!(format!("{:#}", err), "Failed to read packet at offset 936: failed to fill whole buffer");
+    }
+
+    #[cfg(any(feature = "zip", feature = "zlib", feature = "bzip2"))]
+    fn signed_message_tags(armored: &[u8]) -> Vec<Tag> {
+        let msg = read_armored(&mut BufReader::new(armored)).expect("read_armored");
+        let mut parser = Parser::new(msg.as_slice())
+            .with_decompression(compressed::DEFAULT_MAX_RATIO);
+        let mut tags = Vec::new();
+        while let Some((tag, _)) = parser.next_packet().expect("next_packet") {
+            tags.push(tag);
+        }
+        tags
+    }
+
+    #[test]
+    fn compressed_data_is_not_decompressed_by_default() {
+        let msg = include_bytes!("../data/signed_zlib.asc");
+        let msg = read_armored(&mut BufReader::new(&msg[..])).expect("read_armored");
+        let tags = Parser::new(msg.as_slice()).map(|(tag, _)| tag).collect::<Vec<_>>();
+        assert_eq!(tags, vec![Tag::CompressedData]);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn decompress_zip() {
+        assert_eq!(signed_message_tags(include_bytes!("../data/signed_zip.asc")),
+            vec![Tag::OnePassSignature, Tag::LiteralData, Tag::Signature]);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn decompress_zlib() {
+        assert_eq!(signed_message_tags(include_bytes!("../data/signed_zlib.asc")),
+            vec![Tag::OnePassSignature, Tag::LiteralData, Tag::Signature]);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn decompress_bzip2() {
+        assert_eq!(signed_message_tags(include_bytes!("../data/signed_bzip2.asc")),
+            vec![Tag::OnePassSignature, Tag::LiteralData, Tag::Signature]);
+    }
+
+    /// Wrap a packet into an uncompressed compressed data packet
+    fn wrap_uncompressed(packet: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0xa2];
+        buf.extend(&(packet.len() as u32 + 1).to_be_bytes());
+        buf.push(0x00);
+        buf.extend(packet);
+        buf
+    }
+
+    #[test]
+    fn decompress_nested() {
+        let uid = b"\xb4\x03foo";
+        let mut data = uid.to_vec();
+        for _ in 0..MAX_COMPRESSION_DEPTH {
+            data = wrap_uncompressed(&data);
         }
+        data.extend(uid);
+
+        let mut parser = Parser::new(data.as_slice()).with_decompression(MAX_COMPRESSION_DEPTH);
+        assert_eq!(parser.next_packet().unwrap(), Some((Tag::UserID, b"foo".to_vec())));
+        // drained layers are dropped
+        assert_eq!(parser.nested.len(), 1);
+        assert_eq!(parser.next_packet().unwrap(), Some((Tag::UserID, b"foo".to_vec())));
+        assert_eq!(parser.next_packet().unwrap(), None);
+
+        // the ratio applies to all layers together
+        let mut parser = Parser::new(data.as_slice()).with_decompression(1);
+        let err = parser.next_packet().unwrap_err();
+        assert!(format!("{:#}", err).contains("Decompressed data exceeds limit"));
+
+        let data = wrap_uncompressed(&data);
+        let mut parser = Parser::new(data.as_slice()).with_decompression(MAX_COMPRESSION_DEPTH);
+        let err = parser.next_packet().unwrap_err();
+        assert_eq!(err.to_string(), "Compressed data at offset 0 is nested too deeply");
+    }
+
+    #[test]
+    fn decompress_truncated_nested_packet() {
+        let data = wrap_uncompressed(b"\xb4\x03f");
+        let mut parser = Parser::new(data.as_slice()).with_decompression(1);
+        let err = parser.next_packet().unwrap_err();
+        assert_eq!(format!("{:#}", err), "Failed to read packet at offset 0 in compressed data at offset 0: failed to fill whole buffer");
+    }
+
+    #[test]
+    fn decompress_max_alloc() {
+        let data = wrap_uncompressed(&[0xb4, 0x80]);
+        let data = [&data[..], &[0; 0x80]].concat();
+        let mut parser = Parser::with_max_alloc(data.as_slice(), 16).with_decompression(1);
+        assert!(parser.next_packet().is_err());
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn decompress_ratio_limit() {
+        use flate2::{Compression, write::ZlibEncoder};
+
+        let mut w = ZlibEncoder::new(vec![0x02], Compression::best());
+        w.write_all(&[0; 1024 * 1024]).unwrap();
+        let body = w.finish().unwrap();
+        let mut data = vec![0xa2];
+        data.extend(&(body.len() as u32).to_be_bytes());
+        data.extend(&body);
+
+        let mut parser = Parser::new(data.as_slice()).with_decompression(100);
+        let err = parser.next_packet().unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Failed to decompress packet at offset 0: Decompressed data exceeds limit"));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn decompress_nested_bomb() {
+        use flate2::{Compression, write::ZlibEncoder};
+
+        let compress = |data: &[u8]| {
+            let mut w = ZlibEncoder::new(vec![0x02], Compression::best());
+            w.write_all(data).unwrap();
+            let body = w.finish().unwrap();
+            let mut packet = vec![0xa2];
+            packet.extend(&(body.len() as u32).to_be_bytes());
+            packet.extend(&body);
+            packet
+        };
+
+        let mut literal = vec![0xcb, 0xff];
+        literal.extend(&(1024 * 1024 + 6u32).to_be_bytes());
+        literal.extend(&[b'b', 0, 0, 0, 0, 0]);
+        literal.extend(&[0; 1024 * 1024]);
+        let inner = compress(&literal);
+        let data = compress(&inner);
+
+        // enough for each layer on its own, but not for both together
+        let max_ratio = literal.len() / (inner.len() - 5) + 1;
+        assert!(data.len() * max_ratio < literal.len());
+        let mut parser = Parser::new(data.as_slice()).with_decompression(max_ratio);
+        let err = parser.next_packet().unwrap_err();
+        assert!(format!("{:#}", err).contains("Decompressed data exceeds limit"));
+
+        let max_ratio = (literal.len() + inner.len()) / (data.len() - 5) + 1;
+        let mut parser = Parser::new(data.as_slice()).with_decompression(max_ratio);
+        let (tag, body) = parser.next_packet().unwrap().unwrap();
+        assert_eq!(tag, Tag::LiteralData);
+        assert_eq!(body.len(), 1024 * 1024 + 6);
     }
 }
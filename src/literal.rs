@@ -0,0 +1,115 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::errors::*;
+use serde::{Serialize, Deserialize};
+use std::borrow::Cow;
+use std::io::Read;
+
+// https://tools.ietf.org/html/rfc4880#section-5.9
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.9
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataFormat {
+    Binary,
+    Text,
+    Utf8,
+    Mime,
+    /// Local mode, deprecated
+    Local,
+    Unknown(u8),
+}
+
+impl DataFormat {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            b'b' => DataFormat::Binary,
+            b't' => DataFormat::Text,
+            b'u' => DataFormat::Utf8,
+            b'm' => DataFormat::Mime,
+            b'l' | b'1' => DataFormat::Local,
+            b => DataFormat::Unknown(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiteralHeader {
+    pub format: DataFormat,
+    pub filename: Vec<u8>,
+    /// Modification date of the file as unix timestamp, or 0
+    pub date: u32,
+}
+
+impl LiteralHeader {
+    pub fn filename_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.filename)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiteralData {
+    pub header: LiteralHeader,
+    pub data: Vec<u8>,
+}
+
+/// Parse the header of a literal data packet and return it with the
+/// remaining payload, without copying it.
+pub fn parse_header(mut body: &[u8]) -> Result<(LiteralHeader, &[u8])> {
+    let format = DataFormat::from_byte(body.read_u8()?);
+    let len = body.read_u8()? as usize;
+    let mut filename = vec![0; len];
+    body.read_exact(&mut filename)?;
+    let date = body.read_u32::<BigEndian>()?;
+
+    let header = LiteralHeader {
+        format,
+        filename,
+        date,
+    };
+    Ok((header, body))
+}
+
+pub fn parse(body: &[u8]) -> Result<LiteralData> {
+    let (header, data) = parse_header(body)?;
+    Ok(LiteralData {
+        header,
+        data: data.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_literal_data() {
+        let body = b"b\x0bhello.txt.z\x5b\xba\xab\xddhello world\n";
+        let literal = parse(body).expect("parse");
+        assert_eq!(literal, LiteralData {
+            header: LiteralHeader {
+                format: DataFormat::Binary,
+                filename: b"hello.txt.z".to_vec(),
+                date: 1538960349,
+            },
+            data: b"hello world\n".to_vec(),
+        });
+        assert_eq!(literal.header.filename_lossy(), "hello.txt.z");
+    }
+
+    #[test]
+    fn parse_header_borrows_payload() {
+        let body = b"u\x00\x00\x00\x00\x00\xc3\xa4";
+        let (header, mut data) = parse_header(body).expect("parse_header");
+        assert_eq!(header.format, DataFormat::Utf8);
+        assert!(header.filename.is_empty());
+        assert_eq!(header.date, 0);
+
+        let mut buf = String::new();
+        data.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "\u{e4}");
+    }
+
+    #[test]
+    fn parse_truncated_literal_data() {
+        assert!(parse(b"t\x0bhello").is_err());
+        assert!(parse(b"t\x00\x00\x00").is_err());
+    }
+}
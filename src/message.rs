@@ -0,0 +1,277 @@
+use crate::errors::*;
+use crate::literal::{self, LiteralData};
+use crate::onepass::{self, OnePassSignature};
+use crate::packet::Tag;
+use crate::signature::{self, Signature};
+use crate::Parser;
+use std::io::Read;
+
+/// A signature over the message, with the one-pass signature packet that
+/// announced it. Signatures that are placed in front of the data don't
+/// have one.
+#[derive(Debug, PartialEq)]
+pub struct MessageSignature {
+    pub onepass: Option<OnePassSignature>,
+    pub signature: Signature,
+}
+
+/// A literal message, optionally signed. Compressed messages need to be read
+/// with `Parser::with_decompression`, encrypted messages aren't supported.
+// https://tools.ietf.org/html/rfc4880#section-11.3
+#[derive(Debug, PartialEq)]
+pub struct Message {
+    /// Signatures in the order they were announced, the outermost first
+    pub signatures: Vec<MessageSignature>,
+    pub literal: LiteralData,
+}
+
+fn ensure_matches(onepass: &OnePassSignature, sig: &Signature) -> Result<()> {
+    if onepass.sigtype != sig.sigtype
+        || onepass.hash_algo != sig.hash_algo
+        || onepass.pk_algo != sig.pk_algo
+    {
+        bail!("Signature doesn't match one-pass signature from {}", onepass.keyid);
+    }
+
+    if let Some(issuer) = sig.issuer_keyid() {
+        if issuer != onepass.keyid {
+            bail!("Signature from {} doesn't match one-pass signature from {}", issuer, onepass.keyid);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a message from the parser and match every one-pass signature with
+/// its corresponding signature packet.
+pub fn read_message<R: Read>(parser: &mut Parser<R>) -> Result<Message> {
+    let mut onepass_stack = Vec::new();
+    // set if the previous packet was a one-pass signature that claims to be
+    // the last one
+    let mut last_onepass = false;
+    // set if the previous packet was a one-pass signature that announced
+    // another one-pass signature
+    let mut more_onepass = None;
+    let mut signatures = Vec::new();
+    let mut onepass_signatures = Vec::new();
+    let mut literal = None;
+
+    while let Some((tag, body)) = parser.next_packet()? {
+        if tag != Tag::OnePassSignature {
+            if let Some(keyid) = more_onepass.take() {
+                bail!("Expected another one-pass signature after the one-pass signature from {}, found {:?}", keyid, tag);
+            }
+            last_onepass = false;
+        }
+        match tag {
+            Tag::OnePassSignature => {
+                if literal.is_some() {
+                    bail!("Unexpected one-pass signature after literal data");
+                }
+                let onepass = onepass::parse(&body)
+                    .context("Failed to parse one-pass signature")?;
+                if last_onepass {
+                    bail!("Unexpected one-pass signature from {} after the last one-pass signature", onepass.keyid);
+                }
+                last_onepass = onepass.nested;
+                more_onepass = if onepass.nested {
+                    None
+                } else {
+                    Some(onepass.keyid.clone())
+                };
+                onepass_stack.push(onepass);
+            },
+            Tag::Signature => {
+                let signature = signature::parse(&body)
+                    .context("Failed to parse signature")?;
+                if literal.is_none() {
+                    signatures.push(MessageSignature {
+                        onepass: None,
+                        signature,
+                    });
+                } else {
+                    let onepass = onepass_stack.pop()
+                        .context("Signature without one-pass signature after literal data")?;
+                    ensure_matches(&onepass, &signature)?;
+                    onepass_signatures.push(MessageSignature {
+                        onepass: Some(onepass),
+                        signature,
+                    });
+                }
+            },
+            Tag::LiteralData => {
+                if literal.is_some() {
+                    bail!("Message contains more than one literal data packet");
+                }
+                literal = Some(literal::parse(&body)
+                    .context("Failed to parse literal data")?);
+            },
+            Tag::Marker => (),
+            Tag::CompressedData => bail!("Compressed data needs to be decompressed by the parser"),
+            tag => bail!("Unexpected {:?} packet in message", tag),
+        }
+    }
+
+    let literal = literal.context("Message contains no literal data")?;
+    if let Some(onepass) = onepass_stack.pop() {
+        bail!("Missing signature for one-pass signature from {}", onepass.keyid);
+    }
+
+    // signatures are matched from the inside out
+    onepass_signatures.reverse();
+    signatures.extend(onepass_signatures);
+
+    Ok(Message {
+        signatures,
+        literal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::armor::read_armored;
+    use crate::test_util::packets;
+
+    fn serialize(packets: &[(Tag, Vec<u8>)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (tag, body) in packets {
+            buf.push(0xc0 | *tag as u8);
+            buf.push(0xff);
+            buf.extend(&(body.len() as u32).to_be_bytes());
+            buf.extend(body);
+        }
+        buf
+    }
+
+    fn read(packets: &[(Tag, Vec<u8>)]) -> Result<Message> {
+        let data = serialize(packets);
+        read_message(&mut Parser::new(data.as_slice()))
+    }
+
+    #[test]
+    fn read_onepass_signed_message() {
+        let msg = read(&packets(include_bytes!("../data/signed_message.asc"))).expect("read_message");
+        assert_eq!(msg.literal.header.filename_lossy(), "hello.txt");
+        assert_eq!(msg.literal.data, b"hello world\n");
+        assert_eq!(msg.signatures.len(), 1);
+
+        let sig = &msg.signatures[0];
+        assert_eq!(sig.onepass.as_ref().map(|ops| ops.keyid.as_str()), Some("AC7821A64D6134DB"));
+        assert_eq!(sig.signature.fingerprint.as_deref(), Some("1F1A7715C9AD921EF4057C9FAC7821A64D6134DB"));
+    }
+
+    #[test]
+    fn hash_onepass_signed_message() {
+        use crate::signature::SignedData;
+        use sha2::{Digest, Sha256};
+
+        let key = include_bytes!("../data/test_signer.asc");
+        let key = read_armored(&mut &key[..]).expect("read_armored");
+        let cert = crate::Cert::from_packets(Parser::new(key.as_slice())).expect("from_packets");
+
+        let msg = read(&packets(include_bytes!("../data/signed_message.asc"))).expect("read_message");
+        let sig = &msg.signatures[0].signature;
+        assert_eq!(sig.fingerprint, Some(cert.primary.fingerprint().unwrap()));
+
+        let input = sig.hash_input(&SignedData::Document(&msg.literal.data)).expect("hash_input");
+        assert_eq!(Sha256::digest(&input)[..2], sig.hash_prefix);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn read_compressed_message() {
+        let msg = include_bytes!("../data/signed_zip.asc");
+        let msg = read_armored(&mut &msg[..]).expect("read_armored");
+        let mut parser = Parser::new(msg.as_slice())
+            .with_decompression(crate::compressed::DEFAULT_MAX_RATIO);
+        let msg = read_message(&mut parser).expect("read_message");
+        assert_eq!(msg.literal.data, b"hello world\n");
+        assert_eq!(msg.signatures.len(), 1);
+    }
+
+    #[test]
+    fn read_nested_onepass_signatures() {
+        let packets = packets(include_bytes!("../data/signed_message.asc"));
+        let (ops, literal, sig) = (&packets[0], &packets[1], &packets[2]);
+        let mut outer = ops.clone();
+        *outer.1.last_mut().unwrap() = 0;
+
+        let msg = read(&[outer, ops.clone(), literal.clone(), sig.clone(), sig.clone()]).expect("read_message");
+        assert_eq!(msg.signatures.len(), 2);
+        assert!(!msg.signatures[0].onepass.as_ref().unwrap().nested);
+        assert!(msg.signatures[1].onepass.as_ref().unwrap().nested);
+    }
+
+    #[test]
+    fn read_prefixed_signature() {
+        let packets = packets(include_bytes!("../data/signed_message.asc"));
+        let msg = read(&[packets[2].clone(), packets[1].clone()]).expect("read_message");
+        assert_eq!(msg.signatures.len(), 1);
+        assert_eq!(msg.signatures[0].onepass, None);
+    }
+
+    #[test]
+    fn grammar_violations() {
+        let packets = packets(include_bytes!("../data/signed_message.asc"));
+        let (ops, literal, sig) = (&packets[0], &packets[1], &packets[2]);
+
+        let err = read(&[ops.clone(), literal.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Missing signature for one-pass signature from AC7821A64D6134DB");
+        let err = read(&[ops.clone(), literal.clone(), sig.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Signature without one-pass signature after literal data");
+        let err = read(&[ops.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Message contains no literal data");
+        let err = read(&[literal.clone(), literal.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Message contains more than one literal data packet");
+        let err = read(&[literal.clone(), ops.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected one-pass signature after literal data");
+        let err = read(&[ops.clone(), ops.clone(), literal.clone(), sig.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected one-pass signature from AC7821A64D6134DB after the last one-pass signature");
+
+        let mut not_last = ops.clone();
+        *not_last.1.last_mut().unwrap() = 0x00;
+        let err = read(&[not_last, literal.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Expected another one-pass signature after the one-pass signature from AC7821A64D6134DB, found LiteralData");
+    }
+
+    #[test]
+    fn mismatching_onepass_signature() {
+        let packets = packets(include_bytes!("../data/signed_message.asc"));
+        let (ops, literal, sig) = (&packets[0], &packets[1], &packets[2]);
+
+        let mut text = ops.clone();
+        text.1[1] = 0x01;
+        let err = read(&[text, literal.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Signature doesn't match one-pass signature from AC7821A64D6134DB");
+
+        let mut other = ops.clone();
+        other.1[4] = 0x00;
+        let err = read(&[other, literal.clone(), sig.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "Signature from AC7821A64D6134DB doesn't match one-pass signature from 007821A64D6134DB");
+    }
+
+    #[test]
+    fn match_issuer_fingerprint_by_key_version() {
+        use crate::signature::Subpacket;
+
+        let packets = packets(include_bytes!("../data/signed_message.asc"));
+        let mut ops = onepass::parse(&packets[0].1).unwrap();
+        let mut sig = signature::parse(&packets[2].1).unwrap();
+        sig.keyid = None;
+        ensure_matches(&ops, &sig).expect("ensure_matches");
+
+        let v6 = "CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9";
+        for sub in &mut sig.hashed_subpackets {
+            if let Subpacket::IssuerFingerprint { .. } = sub.subpacket {
+                sub.subpacket = Subpacket::IssuerFingerprint {
+                    version: 6,
+                    fingerprint: v6.to_string(),
+                };
+            }
+        }
+        assert!(ensure_matches(&ops, &sig).is_err());
+        ops.keyid = String::from("CB186C4F0609A697");
+        ensure_matches(&ops, &sig).expect("ensure_matches");
+    }
+}
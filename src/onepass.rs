@@ -0,0 +1,115 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::errors::*;
+use crate::algorithm::{HashAlgorithm, PublicKeyAlgorithm};
+use crate::signature::SignatureType;
+use serde::{Serialize, Deserialize};
+use std::io::Read;
+
+// https://tools.ietf.org/html/rfc4880#section-5.4
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.4
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnePassSignature {
+    pub version: u8,
+    pub sigtype: SignatureType,
+    pub hash_algo: HashAlgorithm,
+    pub pk_algo: PublicKeyAlgorithm,
+    /// Key id of the signing key, for v6 it's derived from the fingerprint
+    pub keyid: String,
+    /// Fingerprint of the signing key, only present in v6
+    pub fingerprint: Option<String>,
+    /// Salt of the signature, only present in v6
+    pub salt: Option<Vec<u8>>,
+    /// False if the next packet is another one-pass signature over the
+    /// same data
+    pub nested: bool,
+}
+
+pub fn parse(mut body: &[u8]) -> Result<OnePassSignature> {
+    let version = body.read_u8()?;
+    debug!("one-pass signature version: {:?}", version);
+
+    let sigtype = SignatureType::from_byte(body.read_u8()?);
+    let hash_algo = HashAlgorithm::from_byte(body.read_u8()?);
+    let pk_algo = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+
+    let (keyid, fingerprint, salt) = match version {
+        3 => {
+            let keyid = body.read_u64::<BigEndian>()?;
+            (format!("{:016X}", keyid), None, None)
+        },
+        6 => {
+            let len = body.read_u8()? as usize;
+            let mut salt = vec![0; len];
+            body.read_exact(&mut salt)?;
+            let mut fingerprint = [0; 32];
+            body.read_exact(&mut fingerprint)?;
+            let fingerprint = hex::encode_upper(fingerprint);
+            (fingerprint[..16].to_string(), Some(fingerprint), Some(salt))
+        },
+        _ => bail!("unsupported one-pass signature version: {}", version),
+    };
+
+    let nested = body.read_u8()? != 0;
+    if !body.is_empty() {
+        bail!("one-pass signature has trailing data");
+    }
+
+    Ok(OnePassSignature {
+        version,
+        sigtype,
+        hash_algo,
+        pk_algo,
+        keyid,
+        fingerprint,
+        salt,
+        nested,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_v3_onepass_signature() {
+        let ops = parse(b"\x03\x00\x08\x16\xac\x78\x21\xa6\x4d\x61\x34\xdb\x01").expect("parse");
+        assert_eq!(ops, OnePassSignature {
+            version: 3,
+            sigtype: SignatureType::Binary,
+            hash_algo: HashAlgorithm::Sha256,
+            pk_algo: PublicKeyAlgorithm::Eddsa,
+            keyid: String::from("AC7821A64D6134DB"),
+            fingerprint: None,
+            salt: None,
+            nested: true,
+        });
+
+        let ops = parse(b"\x03\x01\x0a\x01\xac\x78\x21\xa6\x4d\x61\x34\xdb\x00").expect("parse");
+        assert_eq!(ops.sigtype, SignatureType::Text);
+        assert_eq!(ops.hash_algo, HashAlgorithm::Sha512);
+        assert_eq!(ops.pk_algo, PublicKeyAlgorithm::Rsa);
+        assert!(!ops.nested);
+    }
+
+    #[test]
+    fn parse_v6_onepass_signature() {
+        let mut body = vec![0x06, 0x00, 0x0a, 0x1b, 0x04, 1, 2, 3, 4];
+        body.extend(&hex::decode("CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9").unwrap());
+        body.push(0x01);
+
+        let ops = parse(&body).expect("parse");
+        assert_eq!(ops.version, 6);
+        assert_eq!(ops.pk_algo, PublicKeyAlgorithm::Unknown(27));
+        assert_eq!(ops.salt, Some(vec![1, 2, 3, 4]));
+        assert_eq!(ops.fingerprint.as_deref(), Some("CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9"));
+        assert_eq!(ops.keyid, "CB186C4F0609A697");
+        assert!(ops.nested);
+    }
+
+    #[test]
+    fn parse_invalid_onepass_signature() {
+        assert!(parse(b"\x03\x00\x08\x16\xac\x78\x21\xa6\x4d\x61\x34\xdb").is_err());
+        assert!(parse(b"\x03\x00\x08\x16\xac\x78\x21\xa6\x4d\x61\x34\xdb\x01\x00").is_err());
+        assert!(parse(b"\x04\x00\x08\x16\xac\x78\x21\xa6\x4d\x61\x34\xdb\x01").is_err());
+    }
+}
@@ -1,7 +1,15 @@
+use crate::compressed::{self, CompressionAlgorithm};
 use crate::errors::*;
 use crate::encoding::read_length;
+use crate::literal::{self, LiteralData};
+use crate::onepass::{self, OnePassSignature};
+use crate::pubkey::{self, PublicKey};
+use crate::seckey::{self, SecretKey};
+use crate::session_key::{self, PublicKeyEncryptedSessionKey, SymmetricKeyEncryptedSessionKey};
+use crate::signature::{self, Signature};
+use crate::userid::UserId;
 use byteorder::{BigEndian, ReadBytesExt};
-use std::io::Read;
+use std::io::{ErrorKind, Read};
 
 // https://tools.ietf.org/html/rfc4880#section-4.3
 // sed -e "s/ *\(.*\) = \(.*\),/\2 => Some(Packet::\1),/"
@@ -51,6 +59,148 @@ impl Tag {
     }
 }
 
+/// A packet with its body parsed according to its tag. Encrypted data,
+/// trust packets and user attributes are kept as raw bytes.
+#[derive(Debug, PartialEq)]
+pub enum Packet {
+    PublicKey(PublicKey),
+    PublicSubkey(PublicKey),
+    SecretKey(SecretKey),
+    SecretSubkey(SecretKey),
+    Signature(Signature),
+    UserID(UserId),
+    /// The subpackets of the user attribute, like an image
+    UserAttribute(Vec<u8>),
+    LiteralData(LiteralData),
+    /// The algorithm and the compressed data, use `Parser::with_decompression`
+    /// to read the packets inside
+    CompressedData(CompressionAlgorithm, Vec<u8>),
+    OnePassSignature(OnePassSignature),
+    PublicKeyEncryptedSessionKey(PublicKeyEncryptedSessionKey),
+    SymmetricKeyEncryptedSessionKey(SymmetricKeyEncryptedSessionKey),
+    /// Encrypted data without integrity protection
+    SymmetricallyEncryptedData(Vec<u8>),
+    /// The version and the encrypted data
+    SymIntData(u8, Vec<u8>),
+    /// SHA1 hash of the plaintext, only found inside of encrypted data
+    ModificationDetectionCode([u8; 20]),
+    /// Obsolete packet that is ignored
+    Marker,
+    /// Implementation specific data of the local keyring
+    Trust(Vec<u8>),
+    /// Raw body of a packet that couldn't be parsed, returned by
+    /// `Parser::next_typed`
+    Unparsed(Tag, Vec<u8>),
+}
+
+impl Packet {
+    pub fn parse(tag: Tag, body: &[u8]) -> Result<Packet> {
+        match tag {
+            Tag::PublicKey => {
+                let key = pubkey::parse(body)?;
+                Ok(Packet::PublicKey(key))
+            },
+            Tag::PublicSubkey => {
+                let key = pubkey::parse(body)?;
+                Ok(Packet::PublicSubkey(key))
+            },
+            Tag::SecretKey => {
+                let key = seckey::parse(body)?;
+                Ok(Packet::SecretKey(key))
+            },
+            Tag::SecretSubkey => {
+                let key = seckey::parse(body)?;
+                Ok(Packet::SecretSubkey(key))
+            },
+            Tag::Signature => {
+                let sig = signature::parse(body)?;
+                Ok(Packet::Signature(sig))
+            },
+            Tag::UserID => {
+                let uid = UserId::parse(body);
+                Ok(Packet::UserID(uid))
+            },
+            Tag::LiteralData => {
+                let literal = literal::parse(body)?;
+                Ok(Packet::LiteralData(literal))
+            },
+            Tag::OnePassSignature => {
+                let ops = onepass::parse(body)?;
+                Ok(Packet::OnePassSignature(ops))
+            },
+            Tag::PublicKeyEncryptedSessionKey => {
+                let pkesk = session_key::parse_pkesk(body)?;
+                Ok(Packet::PublicKeyEncryptedSessionKey(pkesk))
+            },
+            Tag::SymmetricKeyEncryptedSessionKey => {
+                let skesk = session_key::parse_skesk(body)?;
+                Ok(Packet::SymmetricKeyEncryptedSessionKey(skesk))
+            },
+            Tag::UserAttribute => Ok(Packet::UserAttribute(body.to_vec())),
+            Tag::CompressedData => {
+                let (algorithm, data) = compressed::parse_header(body)?;
+                Ok(Packet::CompressedData(algorithm, data.to_vec()))
+            },
+            // https://tools.ietf.org/html/rfc4880#section-5.7
+            Tag::SymmetricallyEncryptedData => Ok(Packet::SymmetricallyEncryptedData(body.to_vec())),
+            // https://tools.ietf.org/html/rfc4880#section-5.13
+            Tag::SymIntData => {
+                let mut data = body;
+                let version = data.read_u8()?;
+                Ok(Packet::SymIntData(version, data.to_vec()))
+            },
+            // https://tools.ietf.org/html/rfc4880#section-5.14
+            Tag::ModificationDetectionCode => {
+                if body.len() != 20 {
+                    bail!("invalid modification detection code length: {}", body.len());
+                }
+                let mut hash = [0; 20];
+                hash.copy_from_slice(body);
+                Ok(Packet::ModificationDetectionCode(hash))
+            },
+            // https://tools.ietf.org/html/rfc4880#section-5.8
+            Tag::Marker => Ok(Packet::Marker),
+            Tag::Trust => Ok(Packet::Trust(body.to_vec())),
+        }
+    }
+
+    pub fn tag(&self) -> Tag {
+        match self {
+            Packet::PublicKey(_) => Tag::PublicKey,
+            Packet::PublicSubkey(_) => Tag::PublicSubkey,
+            Packet::SecretKey(_) => Tag::SecretKey,
+            Packet::SecretSubkey(_) => Tag::SecretSubkey,
+            Packet::Signature(_) => Tag::Signature,
+            Packet::UserID(_) => Tag::UserID,
+            Packet::UserAttribute(_) => Tag::UserAttribute,
+            Packet::LiteralData(_) => Tag::LiteralData,
+            Packet::CompressedData(..) => Tag::CompressedData,
+            Packet::OnePassSignature(_) => Tag::OnePassSignature,
+            Packet::PublicKeyEncryptedSessionKey(_) => Tag::PublicKeyEncryptedSessionKey,
+            Packet::SymmetricKeyEncryptedSessionKey(_) => Tag::SymmetricKeyEncryptedSessionKey,
+            Packet::SymmetricallyEncryptedData(_) => Tag::SymmetricallyEncryptedData,
+            Packet::SymIntData(..) => Tag::SymIntData,
+            Packet::ModificationDetectionCode(_) => Tag::ModificationDetectionCode,
+            Packet::Marker => Tag::Marker,
+            Packet::Trust(_) => Tag::Trust,
+            Packet::Unparsed(tag, _) => *tag,
+        }
+    }
+}
+
+/// The packet was read completely but its tag isn't known, the stream is
+/// positioned at the next packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownTag(pub u8);
+
+impl std::fmt::Display for UnknownTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown Tag: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownTag {}
+
 fn ensure_alloc_limit(requested: usize, max_alloc: &Option<usize>) -> Result<()> {
     if let Some(max_alloc) = max_alloc {
         if requested > *max_alloc {
@@ -68,10 +218,31 @@ fn ensure_alloc_limit(requested: usize, max_alloc: &Option<usize>) -> Result<()>
 // allocates a vector which might get big.
 
 pub fn read<B: Read>(reader: &mut B, body: &mut Vec<u8>, max_alloc: &Option<usize>) -> Result<Tag> {
+    body.clear();
+    let tag = reader.read_u8()?;
+    read_packet(tag, reader, body, max_alloc)
+}
 
+/// Like `read`, but returns `Ok(None)` if the reader is at a clean end of
+/// stream instead of failing to read the first byte of the next packet.
+pub fn read_next<B: Read>(reader: &mut B, body: &mut Vec<u8>, max_alloc: &Option<usize>) -> Result<Option<Tag>> {
     body.clear();
 
-    let tag = reader.read_u8()?;
+    let mut tag = [0];
+    loop {
+        match reader.read(&mut tag) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    read_packet(tag[0], reader, body, max_alloc)
+        .map(Some)
+}
+
+fn read_packet<B: Read>(tag: u8, reader: &mut B, body: &mut Vec<u8>, max_alloc: &Option<usize>) -> Result<Tag> {
     if tag & 0x80 != 0x80 {
         bail!("0x80 must be set in tag");
     }
@@ -84,9 +255,9 @@ pub fn read<B: Read>(reader: &mut B, body: &mut Vec<u8>, max_alloc: &Option<usiz
         let packet_tag = tag & 0x3f;
 
         let mut l0 = reader.read_u8()?;
-        if l0 >= 224 && l0 < 0xff {
+        if (224..0xff).contains(&l0) {
             trace!("Partial body length....");
-            while l0 >= 224 && l0 < 0xff {
+            while (224..0xff).contains(&l0) {
                 // partial length
                 let len = 1 << (l0 & 0x1f);
                 trace!("Partial read: {:?}", len);
@@ -157,6 +328,6 @@ pub fn read<B: Read>(reader: &mut B, body: &mut Vec<u8>, max_alloc: &Option<usiz
     if let Some(tag) = Tag::from_byte(tag) {
         Ok(tag)
     } else {
-        bail!("Unknown Tag")
+        Err(UnknownTag(tag).into())
     }
 }
@@ -1,24 +1,505 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::algorithm::PublicKeyAlgorithm;
+use crate::errors::*;
+use crate::encoding::ReadValue;
+use serde::{Serialize, Deserialize};
+use md5::Md5;
 use sha1::{Sha1, Digest};
+use sha2::Sha256;
+use std::convert::TryFrom;
 
-pub fn fingerprint(pubkey: &[u8]) -> String {
-    let mut h = Sha1::new();
-    h.update(b"\x99");
-    let len = pubkey.len() as u16;
-    h.update(len.to_be_bytes());
-    h.update(pubkey);
-    let fp = h.finalize();
-    hex::encode_upper(fp)
+// https://tools.ietf.org/html/rfc4880#section-5.5.2
+// https://tools.ietf.org/html/rfc6637#section-9
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PublicKeyMaterial {
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    Dsa {
+        p: Vec<u8>,
+        q: Vec<u8>,
+        g: Vec<u8>,
+        y: Vec<u8>,
+    },
+    Elgamal {
+        p: Vec<u8>,
+        g: Vec<u8>,
+        y: Vec<u8>,
+    },
+    Ecdsa {
+        curve: Vec<u8>,
+        point: Vec<u8>,
+    },
+    Eddsa {
+        curve: Vec<u8>,
+        point: Vec<u8>,
+    },
+    Ecdh {
+        curve: Vec<u8>,
+        point: Vec<u8>,
+        kdf_hash: u8,
+        kdf_cipher: u8,
+    },
+    /// Key material of an unsupported algorithm
+    Unknown(Vec<u8>),
+}
+
+impl PublicKeyMaterial {
+    fn read(algorithm: PublicKeyAlgorithm, body: &mut &[u8]) -> Result<PublicKeyMaterial> {
+        Ok(match algorithm {
+            PublicKeyAlgorithm::Rsa |
+            PublicKeyAlgorithm::RsaEncryptOnly |
+            PublicKeyAlgorithm::RsaSignOnly => PublicKeyMaterial::Rsa {
+                n: body.read_mpi()?.to_vec(),
+                e: body.read_mpi()?.to_vec(),
+            },
+            PublicKeyAlgorithm::Dsa => PublicKeyMaterial::Dsa {
+                p: body.read_mpi()?.to_vec(),
+                q: body.read_mpi()?.to_vec(),
+                g: body.read_mpi()?.to_vec(),
+                y: body.read_mpi()?.to_vec(),
+            },
+            PublicKeyAlgorithm::Elgamal |
+            PublicKeyAlgorithm::ElgamalEncryptOrSign => PublicKeyMaterial::Elgamal {
+                p: body.read_mpi()?.to_vec(),
+                g: body.read_mpi()?.to_vec(),
+                y: body.read_mpi()?.to_vec(),
+            },
+            PublicKeyAlgorithm::Ecdsa => PublicKeyMaterial::Ecdsa {
+                curve: read_oid(body)?.to_vec(),
+                point: body.read_mpi()?.to_vec(),
+            },
+            PublicKeyAlgorithm::Eddsa => PublicKeyMaterial::Eddsa {
+                curve: read_oid(body)?.to_vec(),
+                point: body.read_mpi()?.to_vec(),
+            },
+            PublicKeyAlgorithm::Ecdh => {
+                let curve = read_oid(body)?.to_vec();
+                let point = body.read_mpi()?.to_vec();
+
+                let kdf_len = body.read_u8()?;
+                if kdf_len != 3 {
+                    bail!("unsupported ecdh kdf parameter length: {}", kdf_len);
+                }
+                let _reserved = body.read_u8()?;
+                let kdf_hash = body.read_u8()?;
+                let kdf_cipher = body.read_u8()?;
+
+                PublicKeyMaterial::Ecdh {
+                    curve,
+                    point,
+                    kdf_hash,
+                    kdf_cipher,
+                }
+            },
+            PublicKeyAlgorithm::Unknown(_) => {
+                let material = body.to_vec();
+                *body = &[];
+                PublicKeyMaterial::Unknown(material)
+            },
+        })
+    }
+}
+
+// https://tools.ietf.org/html/rfc6637#section-9
+fn read_oid<'a>(body: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = body.read_u8()? as usize;
+    if len == 0 || len == 0xff {
+        bail!("reserved curve oid length: {}", len);
+    }
+    if len <= body.len() {
+        let (a, b) = body.split_at(len);
+        *body = b;
+        Ok(a)
+    } else {
+        bail!("Index out of bounds")
+    }
+}
+
+/// Return the name of an elliptic curve given its OID
+pub fn curve_name(oid: &[u8]) -> Option<&'static str> {
+    match oid {
+        b"\x2a\x86\x48\xce\x3d\x03\x01\x07" => Some("NIST P-256"),
+        b"\x2b\x81\x04\x00\x22" => Some("NIST P-384"),
+        b"\x2b\x81\x04\x00\x23" => Some("NIST P-521"),
+        b"\x2b\x24\x03\x03\x02\x08\x01\x01\x07" => Some("brainpoolP256r1"),
+        b"\x2b\x24\x03\x03\x02\x08\x01\x01\x0b" => Some("brainpoolP384r1"),
+        b"\x2b\x24\x03\x03\x02\x08\x01\x01\x0d" => Some("brainpoolP512r1"),
+        b"\x2b\x06\x01\x04\x01\xda\x47\x0f\x01" => Some("Ed25519"),
+        b"\x2b\x06\x01\x04\x01\x97\x55\x01\x05\x01" => Some("Curve25519"),
+        _ => None,
+    }
+}
+
+fn curve_bits(oid: &[u8]) -> Option<usize> {
+    match curve_name(oid)? {
+        "NIST P-256" | "brainpoolP256r1" | "Ed25519" | "Curve25519" => Some(256),
+        "NIST P-384" | "brainpoolP384r1" => Some(384),
+        "brainpoolP512r1" => Some(512),
+        "NIST P-521" => Some(521),
+        _ => None,
+    }
+}
+
+fn mpi_bits(mpi: &[u8]) -> usize {
+    let mpi = match mpi.iter().position(|b| *b != 0) {
+        Some(idx) => &mpi[idx..],
+        None => return 0,
+    };
+    mpi.len() * 8 - mpi[0].leading_zeros() as usize
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKey {
+    pub version: u8,
+    /// Creation time as unix timestamp
+    pub created: u32,
+    /// Validity period in days, only present in v2 and v3 keys
+    pub expiration_days: Option<u16>,
+    pub algorithm: PublicKeyAlgorithm,
+    pub material: PublicKeyMaterial,
+}
+
+impl PublicKey {
+    /// Size of the key in bits, as it's usually displayed to users. For
+    /// elliptic curves this is the size of the curve.
+    pub fn bits(&self) -> Option<usize> {
+        match &self.material {
+            PublicKeyMaterial::Rsa { n, .. } => Some(mpi_bits(n)),
+            PublicKeyMaterial::Dsa { p, .. } => Some(mpi_bits(p)),
+            PublicKeyMaterial::Elgamal { p, .. } => Some(mpi_bits(p)),
+            PublicKeyMaterial::Ecdsa { curve, .. } => curve_bits(curve),
+            PublicKeyMaterial::Eddsa { curve, .. } => curve_bits(curve),
+            PublicKeyMaterial::Ecdh { curve, .. } => curve_bits(curve),
+            PublicKeyMaterial::Unknown(_) => None,
+        }
+    }
+}
+
+// https://tools.ietf.org/html/rfc4880#section-5.5.2
+pub fn parse(mut body: &[u8]) -> Result<PublicKey> {
+    read(&mut body)
+}
+
+/// Returns the public key portion of a secret key packet body, the secret
+/// key material that follows is cut off.
+// https://tools.ietf.org/html/rfc4880#section-5.5.3
+pub fn public_part(body: &[u8]) -> Result<&[u8]> {
+    let mut remaining = body;
+    let key = read(&mut remaining)?;
+    // v5 and v6 keys have a length prefix for the public key material
+    if key.version < 5 && matches!(key.material, PublicKeyMaterial::Unknown(_)) {
+        bail!("Can't find the end of the public key material for algorithm: {:?}", key.algorithm);
+    }
+    Ok(&body[..body.len() - remaining.len()])
+}
+
+pub(crate) fn read(body: &mut &[u8]) -> Result<PublicKey> {
+    let version = body.read_u8()?;
+    debug!("pubkey version: {:?}", version);
+
+    match version {
+        2 | 3 => {
+            let created = body.read_u32::<BigEndian>()?;
+            let expiration_days = body.read_u16::<BigEndian>()?;
+            let algorithm = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+            let material = PublicKeyMaterial::read(algorithm, body)?;
+
+            Ok(PublicKey {
+                version,
+                created,
+                expiration_days: Some(expiration_days),
+                algorithm,
+                material,
+            })
+        },
+        4 => {
+            let created = body.read_u32::<BigEndian>()?;
+            let algorithm = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+            let material = PublicKeyMaterial::read(algorithm, body)?;
+
+            Ok(PublicKey {
+                version,
+                created,
+                expiration_days: None,
+                algorithm,
+                material,
+            })
+        },
+        // https://datatracker.ietf.org/doc/html/rfc9580#section-5.5.2.3
+        5 | 6 => {
+            let created = body.read_u32::<BigEndian>()?;
+            let algorithm = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+            let len = body.read_u32::<BigEndian>()? as usize;
+            if len > body.len() {
+                bail!("Index out of bounds");
+            }
+            let mut material = &body[..len];
+            *body = &body[len..];
+            let material = PublicKeyMaterial::read(algorithm, &mut material)?;
+
+            Ok(PublicKey {
+                version,
+                created,
+                expiration_days: None,
+                algorithm,
+                material,
+            })
+        },
+        _ => bail!("unsupported pubkey version: {}", version),
+    }
+}
+
+/// The bytes that are hashed in front of a public key packet body, both for
+/// fingerprints and signatures over keys.
+pub(crate) fn hash_prefix(pubkey: &[u8]) -> Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(5);
+    match pubkey.first() {
+        Some(2) | Some(3) | Some(4) => {
+            let len = u16::try_from(pubkey.len())
+                .context("pubkey is too large")?;
+            prefix.push(0x99);
+            prefix.extend(&len.to_be_bytes());
+        },
+        Some(5) | Some(6) => {
+            let len = u32::try_from(pubkey.len())
+                .context("pubkey is too large")?;
+            prefix.push(if pubkey[0] == 5 { 0x9a } else { 0x9b });
+            prefix.extend(&len.to_be_bytes());
+        },
+        Some(version) => bail!("unsupported pubkey version: {}", version),
+        None => bail!("pubkey is empty"),
+    }
+    Ok(prefix)
+}
+
+// https://tools.ietf.org/html/rfc4880#section-12.2
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.5.4
+pub fn fingerprint(pubkey: &[u8]) -> Result<String> {
+    match pubkey.first() {
+        Some(2) | Some(3) => {
+            let key = parse(pubkey)?;
+            match key.material {
+                PublicKeyMaterial::Rsa { n, e } => {
+                    let mut h = Md5::new();
+                    h.update(n);
+                    h.update(e);
+                    Ok(hex::encode_upper(h.finalize()))
+                },
+                _ => bail!("v3 fingerprint is only defined for rsa keys"),
+            }
+        },
+        Some(4) => {
+            let mut h = Sha1::new();
+            h.update(hash_prefix(pubkey)?);
+            h.update(pubkey);
+            Ok(hex::encode_upper(h.finalize()))
+        },
+        Some(5) | Some(6) => {
+            let mut h = Sha256::new();
+            h.update(hash_prefix(pubkey)?);
+            h.update(pubkey);
+            Ok(hex::encode_upper(h.finalize()))
+        },
+        Some(version) => bail!("unsupported pubkey version: {}", version),
+        None => bail!("pubkey is empty"),
+    }
+}
+
+/// Take the key id from a hex encoded fingerprint of a key with this
+/// version. v4 key ids are the last 8 octets, newer versions use the first 8.
+pub(crate) fn keyid_from_fingerprint(version: u8, fingerprint: &str) -> Option<&str> {
+    match version {
+        4 => fingerprint.get(fingerprint.len().checked_sub(16)?..),
+        _ => fingerprint.get(..16),
+    }
+}
+
+/// Calculate the 64 bit key id that is used to reference this key in the
+/// issuer subpacket of signatures.
+// https://tools.ietf.org/html/rfc4880#section-12.2
+pub fn keyid(pubkey: &[u8]) -> Result<String> {
+    match pubkey.first() {
+        Some(2) | Some(3) => {
+            let key = parse(pubkey)?;
+            match key.material {
+                PublicKeyMaterial::Rsa { n, .. } if n.len() >= 8 => {
+                    Ok(hex::encode_upper(&n[n.len() - 8..]))
+                },
+                PublicKeyMaterial::Rsa { .. } => bail!("rsa modulus is too short"),
+                _ => bail!("v3 key id is only defined for rsa keys"),
+            }
+        },
+        Some(4) => {
+            let fp = fingerprint(pubkey)?;
+            Ok(fp[24..].to_string())
+        },
+        Some(5) | Some(6) => {
+            let fp = fingerprint(pubkey)?;
+            Ok(fp[..16].to_string())
+        },
+        Some(version) => bail!("unsupported pubkey version: {}", version),
+        None => bail!("pubkey is empty"),
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::bodies;
+    use crate::Tag;
 
     #[test]
     fn calculate_fingerprint() {
         let pubkey = b"\x04[\xba\xab\xdd\x01\x08\0\xe0\x0f\xb4=h~z\xf3|\xad\xee\x1c\xcf\x92\xd0\xe4\x01C@d\x91\xf0b\x15\r{!\x8a\xe3w\xeb? \xd1Y\t\xaa7\xea\x17M\xf8\x98X\xb4\r\xf9\xde\xca4\x14\xe7\x17\xdb\xb0\xc8\x17Z,\xce\xd2\x1f\x8e%,\xd6(\xf1m=\x99S6x\xaf\xb0\xb7_n\x7f\x91\xd86\0\x04\xf2V\xb8\xe5\x02\xe8\xfd\xc7\xbd\t\x05^\xd5\xae(\\\xd5\xaa\xea]\x059\x8e\xcc\xfb\x02\xde\xf8m\xb03\x0c\xfcEq\xa0\xc1\xdf\xeaB\x9e?<\x1eX\xa5Hq\x9c\t\xf3\n\\o\xc3)\xab/\xa0P^\xc4\\)#\x12\x0f\x96\xc8\xcc\x8b\xfd\xe6}t\xe4+\xd6\xe4z\xa5\xec\x94}\xce\x03\x91\xfa\x01\xeaT\xe1\xfc\x1c\x15\x1cg\x13.\x9a\xb4\xcc\xe9\xa2\x8b\x80\x08`3\xc4\xa7\xdf\xa8\x02\x14\xe7\xe3\x94m[\xd4\xe1Kf\x15-\x82\xba?\x9eq\x0e\x98\x0b\xabF\xc0\x99\xcb\xc39\x87\xc4\x0c\x16\x96B\x84\x81\xfc\x92\x01\xec\xf7A\x7f\x08+\xc6\x8c\xca\xb7\xaa\xf5\x9c\x1f\xc6l=H\xb3\0\x11\x01\0\x01";
-        let fp = fingerprint(pubkey);
+        let fp = fingerprint(pubkey).expect("fingerprint");
         assert_eq!(fp.as_str(), "CB378ED5E1306C1D3785CA81334D08A1D19D963F");
     }
+
+    fn v3_pubkey() -> Vec<u8> {
+        let mut body = b"\x03\x5b\xba\xab\xdd\x00\x00\x01\x04\x00".to_vec();
+        body.extend(&[0xaa; 120]);
+        body.extend(b"\x01\x23\x45\x67\x89\xab\xcd\xef\x00\x11\x01\x00\x01");
+        body
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc9580#appendix-A.3
+    const V6_PUBKEY: &[u8] = b"\x06\x63\x87\x7f\xe3\x1b\x00\x00\x00\x20\xf9\x4d\xa7\xbb\x48\xd6\x0a\x61\xe5\x67\x70\x6a\x65\x87\xd0\x33\x19\x99\xbb\x9d\x89\x1a\x08\x24\x2e\xad\x84\x54\x3d\xf8\x95\xa3";
+
+    #[test]
+    fn calculate_fingerprint_v3() {
+        let fp = fingerprint(&v3_pubkey()).expect("fingerprint");
+        assert_eq!(fp, "117280E1B5CD311C97938B035A422FF6");
+    }
+
+    #[test]
+    fn calculate_fingerprint_v5() {
+        let mut pubkey = V6_PUBKEY.to_vec();
+        pubkey[0] = 5;
+        let fp = fingerprint(&pubkey).expect("fingerprint");
+        assert_eq!(fp, "C826E56567905F806F41D743ECB8BEA5D13132D9ADE6DDEE7929F29554346C76");
+    }
+
+    #[test]
+    fn calculate_fingerprint_v6() {
+        let fp = fingerprint(V6_PUBKEY).expect("fingerprint");
+        assert_eq!(fp, "CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9");
+        assert_eq!(keyid(V6_PUBKEY).expect("keyid"), "CB186C4F0609A697");
+    }
+
+    #[test]
+    fn fingerprint_too_large() {
+        let mut pubkey = vec![0; 0x10000];
+        pubkey[0] = 4;
+        assert!(fingerprint(&pubkey).is_err());
+        assert!(fingerprint(&pubkey[..0xffff]).is_ok());
+    }
+
+    #[test]
+    fn fingerprint_unknown_version() {
+        assert!(fingerprint(b"\x07\x00").is_err());
+        assert!(fingerprint(b"").is_err());
+    }
+
+    #[test]
+    fn calculate_keyid() {
+        let keys = bodies(include_bytes!("../data/hans_acker.asc"), &[Tag::PublicKey, Tag::PublicSubkey]);
+        assert_eq!(keyid(&keys[0]).expect("keyid"), "334D08A1D19D963F");
+        assert_eq!(keyid(&keys[1]).expect("keyid"), "43E852DB7555FC0E");
+    }
+
+    #[test]
+    fn calculate_keyid_v3() {
+        assert_eq!(keyid(&v3_pubkey()).expect("keyid"), "0123456789ABCDEF");
+    }
+
+    #[test]
+    fn parse_rsa_pubkey() {
+        let keys = bodies(include_bytes!("../data/hans_acker.asc"), &[Tag::PublicKey, Tag::PublicSubkey]);
+        let key = parse(&keys[0]).expect("parse");
+
+        assert_eq!(key.version, 4);
+        assert_eq!(key.created, 1538960349);
+        assert_eq!(key.expiration_days, None);
+        assert_eq!(key.algorithm, PublicKeyAlgorithm::Rsa);
+        assert_eq!(key.bits(), Some(2048));
+        if let PublicKeyMaterial::Rsa { n, e } = &key.material {
+            assert_eq!(n.len(), 256);
+            assert_eq!(e, &[1, 0, 1]);
+        } else {
+            panic!("expected rsa key material");
+        }
+    }
+
+    #[test]
+    fn parse_dsa_elgamal_pubkey() {
+        let keys = bodies(include_bytes!("../data/freebsd.asc"), &[Tag::PublicKey, Tag::PublicSubkey]);
+
+        let key = parse(&keys[0]).expect("parse");
+        assert_eq!(key.version, 4);
+        assert_eq!(key.algorithm, PublicKeyAlgorithm::Dsa);
+        assert_eq!(key.bits(), Some(1024));
+        assert!(matches!(key.material, PublicKeyMaterial::Dsa { .. }));
+
+        let subkey = parse(&keys[1]).expect("parse");
+        assert_eq!(subkey.algorithm, PublicKeyAlgorithm::Elgamal);
+        assert_eq!(subkey.bits(), Some(2048));
+        assert!(matches!(subkey.material, PublicKeyMaterial::Elgamal { .. }));
+    }
+
+    #[test]
+    fn parse_eddsa_pubkey() {
+        let mut body = b"\x04\x5b\xba\xab\xdd\x16\x09\x2b\x06\x01\x04\x01\xda\x47\x0f\x01\x01\x07\x40".to_vec();
+        body.extend(&[0x42; 32]);
+        let key = parse(&body).expect("parse");
+
+        assert_eq!(key.algorithm, PublicKeyAlgorithm::Eddsa);
+        assert_eq!(key.bits(), Some(256));
+        if let PublicKeyMaterial::Eddsa { curve, point } = &key.material {
+            assert_eq!(curve_name(curve), Some("Ed25519"));
+            assert_eq!(point.len(), 33);
+        } else {
+            panic!("expected eddsa key material");
+        }
+    }
+
+    #[test]
+    fn parse_ecdh_pubkey() {
+        let mut body = b"\x04\x5b\xba\xab\xdd\x12\x0a\x2b\x06\x01\x04\x01\x97\x55\x01\x05\x01\x01\x07\x40".to_vec();
+        body.extend(&[0x42; 32]);
+        body.extend(b"\x03\x01\x08\x07");
+        let key = parse(&body).expect("parse");
+
+        assert_eq!(key.material, PublicKeyMaterial::Ecdh {
+            curve: b"\x2b\x06\x01\x04\x01\x97\x55\x01\x05\x01".to_vec(),
+            point: body[19..52].to_vec(),
+            kdf_hash: 8,
+            kdf_cipher: 7,
+        });
+    }
+
+    #[test]
+    fn parse_truncated_pubkey() {
+        let keys = bodies(include_bytes!("../data/hans_acker.asc"), &[Tag::PublicKey, Tag::PublicSubkey]);
+        assert!(parse(&keys[0][..100]).is_err());
+    }
+
+    #[test]
+    fn public_part_of_secret_key() {
+        let keys = bodies(include_bytes!("../data/hans_acker.asc"), &[Tag::PublicKey, Tag::PublicSubkey]);
+        let mut secret = keys[0].clone();
+        // unprotected secret key material
+        secret.extend(&[0x00, 0x00, 0x08, 0xff, 0x00, 0x00]);
+        assert_eq!(public_part(&secret).unwrap(), &keys[0][..]);
+
+        let mut secret = V6_PUBKEY.to_vec();
+        secret.extend(&[0x00, 0x01, 0x02]);
+        assert_eq!(public_part(&secret).unwrap(), V6_PUBKEY);
+
+        let mut secret = b"\x04\x63\x87\x7f\xe3\x1b".to_vec();
+        secret.extend(&[0x42; 32]);
+        assert!(public_part(&secret).is_err());
+    }
 }
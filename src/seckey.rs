@@ -0,0 +1,202 @@
+use byteorder::ReadBytesExt;
+use crate::algorithm::SymmetricAlgorithm;
+use crate::encoding::S2k;
+use crate::errors::*;
+use crate::pubkey::{self, PublicKey, PublicKeyMaterial};
+use serde::{Serialize, Deserialize};
+
+/// How the secret key material is protected, the encrypted key material
+/// itself isn't parsed.
+// https://tools.ietf.org/html/rfc4880#section-5.5.3
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.5.3
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecretKeyProtection {
+    Unprotected,
+    /// s2k usage 253 (AEAD), 254 (SHA1 checksum) or 255 (simple checksum)
+    Encrypted {
+        usage: u8,
+        sym_algo: SymmetricAlgorithm,
+        /// AEAD algorithm, only present for usage 253
+        aead_algo: Option<u8>,
+        s2k: S2k,
+    },
+    /// Legacy format, the usage octet is the symmetric algorithm and the key
+    /// is derived with md5
+    Legacy {
+        sym_algo: SymmetricAlgorithm,
+    },
+    /// The public key algorithm isn't supported, so it's unknown where the
+    /// public key material ends and the secret key material starts
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretKey {
+    pub public: PublicKey,
+    pub protection: SecretKeyProtection,
+}
+
+impl SecretKey {
+    /// Returns the s2k specifier if the key is protected with a passphrase
+    pub fn s2k(&self) -> Option<&S2k> {
+        match &self.protection {
+            SecretKeyProtection::Encrypted { s2k, .. } => Some(s2k),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(mut body: &[u8]) -> Result<SecretKey> {
+    let mut public = pubkey::read(&mut body)?;
+    // v5 and v6 keys have a length prefix for the public key material
+    if public.version < 5 && matches!(public.material, PublicKeyMaterial::Unknown(_)) {
+        // the remaining bytes include the secret key material, don't keep them
+        public.material = PublicKeyMaterial::Unknown(Vec::new());
+        return Ok(SecretKey {
+            public,
+            protection: SecretKeyProtection::Unknown,
+        });
+    }
+    let usage = body.read_u8()?;
+    debug!("secret key s2k usage: {:?}", usage);
+
+    let protection = match usage {
+        0 => SecretKeyProtection::Unprotected,
+        253..=255 => {
+            // newer versions prefix the fields with their length
+            if public.version >= 5 {
+                let len = body.read_u8()? as usize;
+                if len > body.len() {
+                    bail!("Index out of bounds");
+                }
+                body = &body[..len];
+            }
+
+            let sym_algo = SymmetricAlgorithm::from_byte(body.read_u8()?);
+            let aead_algo = if usage == 253 {
+                Some(body.read_u8()?)
+            } else {
+                None
+            };
+            if public.version >= 5 {
+                let len = body.read_u8()? as usize;
+                if len > body.len() {
+                    bail!("Index out of bounds");
+                }
+                body = &body[..len];
+            }
+            let s2k = S2k::read(&mut body)?;
+
+            SecretKeyProtection::Encrypted {
+                usage,
+                sym_algo,
+                aead_algo,
+                s2k,
+            }
+        },
+        sym_algo if public.version < 5 => SecretKeyProtection::Legacy {
+            sym_algo: SymmetricAlgorithm::from_byte(sym_algo),
+        },
+        _ => bail!("unsupported s2k usage for v{} key: {}", public.version, usage),
+    };
+
+    Ok(SecretKey {
+        public,
+        protection,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::GnuS2k;
+    use crate::algorithm::{HashAlgorithm, PublicKeyAlgorithm};
+    use crate::test_util::bodies;
+    use crate::Tag;
+
+    #[test]
+    fn parse_gnu_dummy_key() {
+        let keys = bodies(include_bytes!("../data/secret_subkeys.asc"), &[Tag::SecretKey, Tag::SecretSubkey]);
+        let key = parse(&keys[0]).expect("parse");
+        assert_eq!(pubkey::keyid(pubkey::public_part(&keys[0]).unwrap()).unwrap(), "6778CEA3D230BFD2");
+        assert_eq!(key.protection, SecretKeyProtection::Encrypted {
+            usage: 255,
+            sym_algo: SymmetricAlgorithm::Plaintext,
+            aead_algo: None,
+            s2k: S2k::Gnu {
+                hash: HashAlgorithm::Unknown(0),
+                mode: GnuS2k::Dummy,
+            },
+        });
+        assert!(key.s2k().unwrap().is_gnu_extension());
+    }
+
+    #[test]
+    fn parse_protected_key() {
+        let keys = bodies(include_bytes!("../data/secret_subkeys.asc"), &[Tag::SecretKey, Tag::SecretSubkey]);
+        let key = parse(&keys[1]).expect("parse");
+        assert_eq!(key.public.algorithm, PublicKeyAlgorithm::Ecdh);
+        match &key.protection {
+            SecretKeyProtection::Encrypted { usage, sym_algo, s2k, .. } => {
+                assert_eq!(*usage, 254);
+                assert_eq!(*sym_algo, SymmetricAlgorithm::Aes128);
+                assert_eq!(s2k.iterations(), Some(65011712));
+            },
+            protection => panic!("unexpected protection: {:?}", protection),
+        }
+    }
+
+    #[test]
+    fn parse_unprotected_and_legacy_key() {
+        let keys = bodies(include_bytes!("../data/secret_subkeys.asc"), &[Tag::SecretKey, Tag::SecretSubkey]);
+        let public = pubkey::public_part(&keys[1]).unwrap().to_vec();
+
+        let key = parse(&[&public[..], &[0x00, 0x01, 0x02]].concat()).expect("parse");
+        assert_eq!(key.protection, SecretKeyProtection::Unprotected);
+        assert_eq!(key.s2k(), None);
+
+        let key = parse(&[&public[..], &[0x03, 0x01, 0x02]].concat()).expect("parse");
+        assert_eq!(key.protection, SecretKeyProtection::Legacy {
+            sym_algo: SymmetricAlgorithm::Cast5,
+        });
+    }
+
+    #[test]
+    fn parse_unknown_algorithm_key() {
+        let mut body = b"\x04\x63\x87\x7f\xe3\x1b".to_vec();
+        body.extend(&[0x42; 32]);
+        body.extend(&[0xfe, 0x07, 0x03, 0x08]);
+
+        let key = parse(&body).expect("parse");
+        assert_eq!(key.public.algorithm, PublicKeyAlgorithm::Unknown(27));
+        assert_eq!(key.protection, SecretKeyProtection::Unknown);
+        assert_eq!(key.public.material, PublicKeyMaterial::Unknown(Vec::new()));
+        assert_eq!(key.s2k(), None);
+        assert!(pubkey::public_part(&body).is_err());
+    }
+
+    #[test]
+    fn parse_v6_protected_key() {
+        let mut body = b"\x06\x63\x87\x7f\xe3\x1b\x00\x00\x00\x20".to_vec();
+        body.extend(&[0x42; 32]);
+        body.extend(&[0xfd, 0x17, 0x09, 0x02, 0x14, 0x04]);
+        body.extend(&[0xaa; 16]);
+        body.extend(&[0x01, 0x04, 0x15]);
+        body.extend(&[0xbb; 48]);
+
+        let key = parse(&body).expect("parse");
+        assert_eq!(key.public.version, 6);
+        match &key.protection {
+            SecretKeyProtection::Encrypted { usage, sym_algo, aead_algo, s2k } => {
+                assert_eq!(*usage, 253);
+                assert_eq!(*sym_algo, SymmetricAlgorithm::Aes256);
+                assert_eq!(*aead_algo, Some(2));
+                assert_eq!(s2k.memory_kib(), Some(2 * 1024 * 1024));
+            },
+            protection => panic!("unexpected protection: {:?}", protection),
+        }
+
+        body[42] = 0x03;
+        assert!(parse(&body).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::algorithm::{PublicKeyAlgorithm, SymmetricAlgorithm};
+use crate::encoding::{ReadValue, S2k};
+use crate::errors::*;
+use crate::pubkey;
+use serde::{Serialize, Deserialize};
+use std::io::Read;
+
+const WILDCARD_KEYID: &str = "0000000000000000";
+
+// https://tools.ietf.org/html/rfc4880#section-5.1
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeyEncryptedSessionKey {
+    pub version: u8,
+    /// Key id of the recipient, all zeros if the recipient is hidden
+    pub keyid: String,
+    /// Fingerprint of the recipient, only present in v6
+    pub fingerprint: Option<String>,
+    pub pk_algo: PublicKeyAlgorithm,
+    /// The encrypted session key. For ecdh this is the ephemeral point
+    /// followed by the wrapped key, for unknown algorithms the remaining
+    /// body is kept as a single element.
+    pub mpis: Vec<Vec<u8>>,
+}
+
+impl PublicKeyEncryptedSessionKey {
+    /// Returns true if the recipient is hidden with a wildcard key id
+    pub fn is_wildcard(&self) -> bool {
+        self.keyid == WILDCARD_KEYID
+    }
+}
+
+fn read_encrypted_key(pk_algo: PublicKeyAlgorithm, mut body: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mpis = match pk_algo {
+        PublicKeyAlgorithm::Rsa | PublicKeyAlgorithm::RsaEncryptOnly => {
+            vec![body.read_mpi()?.to_vec()]
+        },
+        PublicKeyAlgorithm::Elgamal | PublicKeyAlgorithm::ElgamalEncryptOrSign => {
+            vec![body.read_mpi()?.to_vec(), body.read_mpi()?.to_vec()]
+        },
+        // https://tools.ietf.org/html/rfc6637#section-10
+        PublicKeyAlgorithm::Ecdh => {
+            let point = body.read_mpi()?.to_vec();
+            let len = body.read_u8()? as usize;
+            let mut wrapped = vec![0; len];
+            body.read_exact(&mut wrapped)?;
+            vec![point, wrapped]
+        },
+        _ => {
+            let data = body.to_vec();
+            body = &[];
+            vec![data]
+        },
+    };
+
+    if !body.is_empty() {
+        bail!("encrypted session key has trailing data");
+    }
+    Ok(mpis)
+}
+
+pub fn parse_pkesk(mut body: &[u8]) -> Result<PublicKeyEncryptedSessionKey> {
+    let version = body.read_u8()?;
+    debug!("pkesk version: {:?}", version);
+
+    let (keyid, fingerprint) = match version {
+        3 => {
+            let keyid = body.read_u64::<BigEndian>()?;
+            (format!("{:016X}", keyid), None)
+        },
+        6 => {
+            let len = body.read_u8()? as usize;
+            if len == 0 {
+                (String::from(WILDCARD_KEYID), None)
+            } else {
+                let key_version = body.read_u8()?;
+                let mut fingerprint = vec![0; len - 1];
+                body.read_exact(&mut fingerprint)?;
+                let fingerprint = hex::encode_upper(fingerprint);
+                let keyid = pubkey::keyid_from_fingerprint(key_version, &fingerprint)
+                    .context("recipient fingerprint is too short")?
+                    .to_string();
+                (keyid, Some(fingerprint))
+            }
+        },
+        _ => bail!("unsupported pkesk version: {}", version),
+    };
+
+    let pk_algo = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+    let mpis = read_encrypted_key(pk_algo, body)?;
+
+    Ok(PublicKeyEncryptedSessionKey {
+        version,
+        keyid,
+        fingerprint,
+        pk_algo,
+        mpis,
+    })
+}
+
+// https://tools.ietf.org/html/rfc4880#section-5.3
+// https://datatracker.ietf.org/doc/html/rfc9580#section-5.3
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymmetricKeyEncryptedSessionKey {
+    pub version: u8,
+    pub sym_algo: SymmetricAlgorithm,
+    /// AEAD algorithm, only present in v6
+    pub aead_algo: Option<u8>,
+    pub s2k: S2k,
+    /// Encrypted session key, empty if the s2k output is used directly. In
+    /// v6 this includes the IV and the authentication tag.
+    pub encrypted_key: Vec<u8>,
+}
+
+pub fn parse_skesk(mut body: &[u8]) -> Result<SymmetricKeyEncryptedSessionKey> {
+    let version = body.read_u8()?;
+    debug!("skesk version: {:?}", version);
+
+    match version {
+        4 => {
+            let sym_algo = SymmetricAlgorithm::from_byte(body.read_u8()?);
+            let s2k = S2k::read(&mut body)?;
+            Ok(SymmetricKeyEncryptedSessionKey {
+                version,
+                sym_algo,
+                aead_algo: None,
+                s2k,
+                encrypted_key: body.to_vec(),
+            })
+        },
+        6 => {
+            let len = body.read_u8()? as usize;
+            if len > body.len() {
+                bail!("Index out of bounds");
+            }
+            let (mut fields, encrypted_key) = body.split_at(len);
+            let sym_algo = SymmetricAlgorithm::from_byte(fields.read_u8()?);
+            let aead_algo = fields.read_u8()?;
+            let s2k_len = fields.read_u8()? as usize;
+            if s2k_len > fields.len() {
+                bail!("Index out of bounds");
+            }
+            let mut s2k = &fields[..s2k_len];
+            let s2k = S2k::read(&mut s2k)?;
+            // the remaining fields are the IV, keep them with the key
+            let mut encrypted = fields[s2k_len..].to_vec();
+            encrypted.extend(encrypted_key);
+            Ok(SymmetricKeyEncryptedSessionKey {
+                version,
+                sym_algo,
+                aead_algo: Some(aead_algo),
+                s2k,
+                encrypted_key: encrypted,
+            })
+        },
+        _ => bail!("unsupported skesk version: {}", version),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::HashAlgorithm;
+    use crate::test_util::bodies;
+    use crate::Tag;
+
+    #[test]
+    fn parse_rsa_pkesk() {
+        let bodies = bodies(include_bytes!("../data/encrypted_message.asc"), &[Tag::PublicKeyEncryptedSessionKey]);
+        let pkesk = parse_pkesk(&bodies[0]).expect("parse_pkesk");
+        assert_eq!(pkesk.version, 3);
+        assert_eq!(pkesk.keyid, "43E852DB7555FC0E");
+        assert!(!pkesk.is_wildcard());
+        assert_eq!(pkesk.pk_algo, PublicKeyAlgorithm::Rsa);
+        assert_eq!(pkesk.mpis.len(), 1);
+        assert_eq!(pkesk.mpis[0].len(), 256);
+    }
+
+    #[test]
+    fn parse_hidden_ecdh_pkesk() {
+        let bodies = bodies(include_bytes!("../data/encrypted_message.asc"), &[Tag::PublicKeyEncryptedSessionKey]);
+        let pkesk = parse_pkesk(&bodies[1]).expect("parse_pkesk");
+        assert_eq!(pkesk.keyid, "0000000000000000");
+        assert!(pkesk.is_wildcard());
+        assert_eq!(pkesk.pk_algo, PublicKeyAlgorithm::Ecdh);
+        assert_eq!(pkesk.mpis.len(), 2);
+        assert_eq!(pkesk.mpis[0].len(), 33);
+        assert_eq!(pkesk.mpis[1].len(), 48);
+    }
+
+    #[test]
+    fn parse_v6_pkesk() {
+        let mut body = vec![0x06, 0x21, 0x06];
+        body.extend(&hex::decode("CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9").unwrap());
+        body.push(25);
+        body.extend(&[0x42; 32]);
+        let pkesk = parse_pkesk(&body).expect("parse_pkesk");
+        assert_eq!(pkesk.keyid, "CB186C4F0609A697");
+        assert_eq!(pkesk.fingerprint.as_deref(), Some("CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9"));
+        assert_eq!(pkesk.pk_algo, PublicKeyAlgorithm::Unknown(25));
+        assert_eq!(pkesk.mpis, vec![vec![0x42; 32]]);
+
+        let pkesk = parse_pkesk(&[0x06, 0x00, 25, 0x42]).expect("parse_pkesk");
+        assert!(pkesk.is_wildcard());
+        assert_eq!(pkesk.fingerprint, None);
+    }
+
+    #[test]
+    fn parse_truncated_pkesk() {
+        let bodies = bodies(include_bytes!("../data/encrypted_message.asc"), &[Tag::PublicKeyEncryptedSessionKey]);
+        assert!(parse_pkesk(&bodies[0][..100]).is_err());
+        assert!(parse_pkesk(&bodies[1][..bodies[1].len() - 1]).is_err());
+    }
+
+    #[test]
+    fn parse_skesk() {
+        let bodies = bodies(include_bytes!("../data/encrypted_message.asc"), &[Tag::SymmetricKeyEncryptedSessionKey]);
+        let skesk = super::parse_skesk(&bodies[0]).expect("parse_skesk");
+        assert_eq!(skesk.version, 4);
+        assert_eq!(skesk.sym_algo, SymmetricAlgorithm::Aes256);
+        assert_eq!(skesk.aead_algo, None);
+        match skesk.s2k {
+            S2k::IteratedSalted { hash, .. } => assert_eq!(hash, HashAlgorithm::Sha1),
+            s2k => panic!("unexpected s2k: {:?}", s2k),
+        }
+        assert_eq!(skesk.encrypted_key.len(), 33);
+    }
+
+    #[test]
+    fn parse_v6_skesk() {
+        let mut body = vec![0x06, 0x1a, 0x09, 0x02, 0x0b, 0x03, 0x08];
+        body.extend(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        body.push(0x60);
+        body.extend(&[0xaa; 12]);
+        body.extend(&[0xbb; 48]);
+        let skesk = super::parse_skesk(&body).expect("parse_skesk");
+        assert_eq!(skesk.sym_algo, SymmetricAlgorithm::Aes256);
+        assert_eq!(skesk.aead_algo, Some(2));
+        assert_eq!(skesk.s2k, S2k::IteratedSalted {
+            hash: HashAlgorithm::Sha256,
+            salt: [1, 2, 3, 4, 5, 6, 7, 8],
+            count: 0x60,
+        });
+        assert_eq!(skesk.encrypted_key.len(), 60);
+    }
+}
@@ -1,86 +1,524 @@
 use byteorder::{BigEndian, ReadBytesExt};
+use crate::algorithm::{HashAlgorithm, PublicKeyAlgorithm};
 use crate::errors::*;
-use crate::encoding::{ReadValue, read_length};
+use crate::encoding::{ReadValue, read_subpacket_length};
+use crate::pubkey::{self, PublicKey};
+use crate::verify::Verifier;
 use serde::{Serialize, Deserialize};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+// https://tools.ietf.org/html/rfc4880#section-5.2.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureType {
+    Binary,
+    Text,
+    Standalone,
+    GenericCertification,
+    PersonaCertification,
+    CasualCertification,
+    PositiveCertification,
+    SubkeyBinding,
+    PrimaryKeyBinding,
+    DirectKey,
+    KeyRevocation,
+    SubkeyRevocation,
+    CertificationRevocation,
+    Timestamp,
+    ThirdPartyConfirmation,
+    Unknown(u8),
+}
+
+impl SignatureType {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            0x00 => SignatureType::Binary,
+            0x01 => SignatureType::Text,
+            0x02 => SignatureType::Standalone,
+            0x10 => SignatureType::GenericCertification,
+            0x11 => SignatureType::PersonaCertification,
+            0x12 => SignatureType::CasualCertification,
+            0x13 => SignatureType::PositiveCertification,
+            0x18 => SignatureType::SubkeyBinding,
+            0x19 => SignatureType::PrimaryKeyBinding,
+            0x1f => SignatureType::DirectKey,
+            0x20 => SignatureType::KeyRevocation,
+            0x28 => SignatureType::SubkeyRevocation,
+            0x30 => SignatureType::CertificationRevocation,
+            0x40 => SignatureType::Timestamp,
+            0x50 => SignatureType::ThirdPartyConfirmation,
+            b => SignatureType::Unknown(b),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Signature {
+    pub version: u8,
+    pub sigtype: SignatureType,
+    pub pk_algo: PublicKeyAlgorithm,
+    pub hash_algo: HashAlgorithm,
+    /// The left 16 bits of the signed hash value
+    pub hash_prefix: [u8; 2],
+    /// The algorithm specific signature values, like `m**d mod n` for RSA
+    /// or `r` and `s` for DSA
+    pub mpis: Vec<Vec<u8>>,
+    /// Subpackets of v4 signatures that are covered by the signature
+    pub hashed_subpackets: Vec<SignatureSubpacket>,
+    /// Subpackets of v4 signatures that are not covered by the signature and
+    /// may have been modified by anybody
+    pub unhashed_subpackets: Vec<SignatureSubpacket>,
+    /// Creation time as unix timestamp, only taken from the hashed area
+    pub created: Option<u32>,
+    /// Issuer key id, the hashed area takes precedence
     pub keyid: Option<String>,
+    /// Issuer fingerprint, the hashed area takes precedence
     pub fingerprint: Option<String>,
+    /// The part of the signature packet that is included in the hash
+    pub hashed_data: Vec<u8>,
 }
 
-pub fn parse(mut body: &[u8]) -> Result<Signature> {
+/// The data a signature is calculated over, depending on the signature type.
+/// Keys are passed as the body of the public key packet.
+// https://tools.ietf.org/html/rfc4880#section-5.2.4
+#[derive(Debug, Clone, Copy)]
+pub enum SignedData<'a> {
+    /// A binary document, or a text document with canonical line endings
+    Document(&'a [u8]),
+    /// Direct key signatures and key revocations
+    Key(&'a [u8]),
+    /// Certifications and certification revocations
+    UserId {
+        key: &'a [u8],
+        userid: &'a [u8],
+    },
+    UserAttribute {
+        key: &'a [u8],
+        attribute: &'a [u8],
+    },
+    /// Subkey bindings, primary key bindings and subkey revocations
+    Subkey {
+        key: &'a [u8],
+        subkey: &'a [u8],
+    },
+    /// Standalone and timestamp signatures don't cover any data
+    Empty,
+}
+
+fn write_key<W: Write>(w: &mut W, key: &[u8]) -> Result<()> {
+    w.write_all(&pubkey::hash_prefix(key)?)?;
+    w.write_all(key)?;
+    Ok(())
+}
+
+fn write_len<W: Write>(w: &mut W, data: &[u8]) -> Result<()> {
+    let len = u32::try_from(data.len())
+        .context("data is too large")?;
+    w.write_all(&len.to_be_bytes())?;
+    Ok(())
+}
+
+fn read_mpis(mut body: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut mpis = Vec::new();
+    while !body.is_empty() {
+        let mpi = body.read_mpi()?;
+        mpis.push(mpi.to_vec());
+    }
+    Ok(mpis)
+}
+
+// Limit the nesting of embedded signatures
+const MAX_DEPTH: usize = 4;
+
+impl Signature {
+    /// Iterate over all subpackets, hashed area first
+    pub fn subpackets(&self) -> impl Iterator<Item=&SignatureSubpacket> {
+        self.hashed_subpackets.iter()
+            .chain(self.unhashed_subpackets.iter())
+    }
+
+    /// Return the first match in the hashed area, falling back to the
+    /// unhashed area.
+    pub fn find_subpacket<'a, T, F>(&'a self, f: F) -> Option<T>
+        where F: Fn(&'a Subpacket) -> Option<T>
+    {
+        self.subpackets()
+            .find_map(|sub| f(&sub.subpacket))
+    }
+
+    /// Return the first match in the hashed area, ignoring the unhashed area.
+    pub fn find_hashed_subpacket<'a, T, F>(&'a self, f: F) -> Option<T>
+        where F: Fn(&'a Subpacket) -> Option<T>
+    {
+        self.hashed_subpackets.iter()
+            .find_map(|sub| f(&sub.subpacket))
+    }
+
+    /// The issuer key id, derived from the issuer fingerprint if the
+    /// signature has no issuer subpacket
+    pub fn issuer_keyid(&self) -> Option<&str> {
+        self.keyid.as_deref().or_else(|| self.find_subpacket(|sub| match sub {
+            Subpacket::IssuerFingerprint { version, fingerprint } => {
+                pubkey::keyid_from_fingerprint(*version, fingerprint)
+            },
+            _ => None,
+        }))
+    }
+
+    /// The bytes that are appended to the signed data before hashing. For v4
+    /// signatures this includes the final `0x04 0xFF` trailer.
+    // https://tools.ietf.org/html/rfc4880#section-5.2.4
+    pub fn hash_trailer(&self) -> Result<Vec<u8>> {
+        let mut trailer = self.hashed_data.clone();
+        if self.version == 4 {
+            trailer.extend(&[0x04, 0xff]);
+            write_len(&mut trailer, &self.hashed_data)?;
+        }
+        Ok(trailer)
+    }
+
+    /// Write the exact byte stream that needs to be hashed to verify this
+    /// signature, so it can be checked with an external crypto library.
+    pub fn write_hash_input<W: Write>(&self, data: &SignedData, w: &mut W) -> Result<()> {
+        match data {
+            SignedData::Document(data) => w.write_all(data)?,
+            SignedData::Key(key) => write_key(w, key)?,
+            SignedData::UserId { key, userid } => {
+                write_key(w, key)?;
+                if self.version == 4 {
+                    w.write_all(&[0xb4])?;
+                    write_len(w, userid)?;
+                }
+                w.write_all(userid)?;
+            },
+            SignedData::UserAttribute { key, attribute } => {
+                write_key(w, key)?;
+                if self.version == 4 {
+                    w.write_all(&[0xd1])?;
+                    write_len(w, attribute)?;
+                }
+                w.write_all(attribute)?;
+            },
+            SignedData::Subkey { key, subkey } => {
+                write_key(w, key)?;
+                write_key(w, subkey)?;
+            },
+            SignedData::Empty => (),
+        }
+        w.write_all(&self.hash_trailer()?)?;
+        Ok(())
+    }
+
+    /// Like `write_hash_input`, but collect the bytes into a buffer.
+    pub fn hash_input(&self, data: &SignedData) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_hash_input(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Assemble the hashed data and check the signature with the given
+    /// verifier. Returns false if `key` uses a different algorithm or the
+    /// signature has unknown critical subpackets.
+    pub fn verify<V: Verifier + ?Sized>(&self, verifier: &V, key: &PublicKey, data: &SignedData) -> Result<bool> {
+        if self.has_unknown_critical_subpackets() {
+            debug!("Signature contains unknown critical subpacket");
+            return Ok(false);
+        }
+        if key.algorithm != self.pk_algo {
+            debug!("Signature algorithm {:?} doesn't match key algorithm {:?}", self.pk_algo, key.algorithm);
+            return Ok(false);
+        }
+        let input = self.hash_input(data)?;
+        verifier.verify(&self.hash_algo, &key.material, &input, &self.mpis)
+    }
+
+    /// Returns true if the signature, or a signature embedded in it, contains
+    /// a subpacket that is marked as critical but isn't known to this parser.
+    /// Those signatures should be considered invalid.
+    // https://tools.ietf.org/html/rfc4880#section-5.2.3.1
+    pub fn has_unknown_critical_subpackets(&self) -> bool {
+        self.subpackets()
+            .any(|sub| match &sub.subpacket {
+                Subpacket::Unknown { .. } => sub.critical,
+                Subpacket::EmbeddedSignature(sig) => sig.has_unknown_critical_subpackets(),
+                _ => false,
+            })
+    }
+}
+
+pub fn parse(body: &[u8]) -> Result<Signature> {
+    parse_nested(body, 0)
+}
+
+/// Like `parse`, but reject signatures with unknown critical subpackets.
+pub fn parse_strict(body: &[u8]) -> Result<Signature> {
+    let sig = parse(body)?;
+    if sig.has_unknown_critical_subpackets() {
+        bail!("signature contains unknown critical subpacket");
+    }
+    Ok(sig)
+}
+
+fn parse_nested(mut body: &[u8], depth: usize) -> Result<Signature> {
     let initial_body = body;
     let version = body.read_u8()?;
-    let mut keyid = None;
-    let mut fingerprint = None;
 
     debug!("signature version: {:?}", version);
     match version {
-        // 3 => TODO
+        // https://tools.ietf.org/html/rfc4880#section-5.2.2
+        2 | 3 => {
+            let hashed_len = body.read_u8()?;
+            if hashed_len != 5 {
+                bail!("invalid length of hashed material: {}", hashed_len);
+            }
+            let sigtype = SignatureType::from_byte(body.read_u8()?);
+            let created = body.read_u32::<BigEndian>()?;
+            let issuer = body.read_u64::<BigEndian>()?;
+            let pk_algo = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+            let hash_algo = HashAlgorithm::from_byte(body.read_u8()?);
+
+            let mut hash_prefix = [0; 2];
+            body.read_exact(&mut hash_prefix)?;
+            let mpis = read_mpis(body)?;
+
+            Ok(Signature {
+                version,
+                sigtype,
+                pk_algo,
+                hash_algo,
+                hash_prefix,
+                mpis,
+                hashed_subpackets: Vec::new(),
+                unhashed_subpackets: Vec::new(),
+                created: Some(created),
+                keyid: Some(format!("{:016X}", issuer)),
+                fingerprint: None,
+                hashed_data: initial_body[2..7].to_vec(),
+            })
+        },
         4 => {
-            let _sigtype = body.read_u8()?;
-            let _pk_algo = body.read_u8()?;
-            let _hash_algo = body.read_u8()?;
+            let sigtype = SignatureType::from_byte(body.read_u8()?);
+            let pk_algo = PublicKeyAlgorithm::from_byte(body.read_u8()?);
+            let hash_algo = HashAlgorithm::from_byte(body.read_u8()?);
 
             let mut hashed_subpacket = body.read_string()?;
             let initial_len = initial_body.len() - body.len();
             debug!("initial_len: {:?}", initial_len);
             let mut unhashed_subpacket = body.read_string()?;
 
+            let mut hashed_subpackets = Vec::new();
             while !hashed_subpacket.is_empty() {
-                let sub = Subpacket::read(&mut hashed_subpacket)?;
-                match sub {
-                    Some(Subpacket::Issuer(i)) => keyid = Some(i),
-                    Some(Subpacket::IssuerFingerprint(fp)) => fingerprint = Some(fp),
-                    _ => (),
-                }
+                let sub = Subpacket::read(&mut hashed_subpacket, depth)?;
+                hashed_subpackets.push(sub);
             }
+            let mut unhashed_subpackets = Vec::new();
             while !unhashed_subpacket.is_empty() {
-                let sub = Subpacket::read(&mut unhashed_subpacket)?;
-                match sub {
-                    Some(Subpacket::Issuer(i)) => keyid = Some(i),
-                    Some(Subpacket::IssuerFingerprint(fp)) => fingerprint = Some(fp),
-                    _ => (),
-                }
+                let sub = Subpacket::read(&mut unhashed_subpacket, depth)?;
+                unhashed_subpackets.push(sub);
             }
 
-            Ok(Signature {
-                keyid,
-                fingerprint,
-            })
+            let mut hash_prefix = [0; 2];
+            body.read_exact(&mut hash_prefix)?;
+            let mpis = read_mpis(body)?;
+
+            let mut sig = Signature {
+                version,
+                sigtype,
+                pk_algo,
+                hash_algo,
+                hash_prefix,
+                mpis,
+                hashed_subpackets,
+                unhashed_subpackets,
+                created: None,
+                keyid: None,
+                fingerprint: None,
+                hashed_data: initial_body[..initial_len].to_vec(),
+            };
+
+            sig.created = sig.find_hashed_subpacket(|sub| match sub {
+                Subpacket::SignatureCreationTime(t) => Some(*t),
+                _ => None,
+            });
+            sig.keyid = sig.find_subpacket(|sub| match sub {
+                Subpacket::Issuer(i) => Some(i.clone()),
+                _ => None,
+            });
+            sig.fingerprint = sig.find_subpacket(|sub| match sub {
+                Subpacket::IssuerFingerprint { fingerprint, .. } => Some(fingerprint.clone()),
+                _ => None,
+            });
+
+            Ok(sig)
         },
         _ => bail!("unsupported signature version: {}", version),
     }
 }
 
+// https://tools.ietf.org/html/rfc4880#section-5.2.3.1
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Subpacket {
+    SignatureCreationTime(u32),
+    /// Seconds after the creation time
+    SignatureExpirationTime(u32),
+    ExportableCertification(bool),
+    TrustSignature {
+        level: u8,
+        amount: u8,
+    },
+    RegularExpression(String),
+    Revocable(bool),
+    /// Seconds after the key creation time
+    KeyExpirationTime(u32),
+    PreferredSymmetricAlgorithms(Vec<u8>),
+    RevocationKey {
+        class: u8,
+        algorithm: PublicKeyAlgorithm,
+        fingerprint: String,
+    },
     Issuer(String),
-    IssuerFingerprint(String),
-    Unknown,
+    NotationData {
+        human_readable: bool,
+        name: String,
+        value: Vec<u8>,
+    },
+    PreferredHashAlgorithms(Vec<u8>),
+    PreferredCompressionAlgorithms(Vec<u8>),
+    KeyServerPreferences(Vec<u8>),
+    PreferredKeyServer(String),
+    PrimaryUserId(bool),
+    PolicyUri(String),
+    KeyFlags(Vec<u8>),
+    SignersUserId(String),
+    ReasonForRevocation {
+        code: u8,
+        reason: String,
+    },
+    Features(Vec<u8>),
+    EmbeddedSignature(Box<Signature>),
+    IssuerFingerprint {
+        /// Version of the issuer key
+        version: u8,
+        fingerprint: String,
+    },
+    Unknown {
+        tag: u8,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignatureSubpacket {
+    /// If set, a signature with this subpacket must be considered invalid if
+    /// the subpacket isn't understood
+    pub critical: bool,
+    pub subpacket: Subpacket,
+}
+
+fn lossy_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
 }
 
 impl Subpacket {
-    fn read(packet: &mut &[u8]) -> Result<Option<Subpacket>> {
+    fn read(packet: &mut &[u8], depth: usize) -> Result<SignatureSubpacket> {
         let p0 = packet.read_u8()? as usize;
-        let len = read_length(p0, packet)?;
+        let len = read_subpacket_length(p0, packet)?;
         if len <= packet.len() {
             let (mut a, b) = packet.split_at(len);
             *packet = b;
 
-            match a.read_u8()? {
+            let tag = a.read_u8()?;
+            let critical = tag & 0x80 != 0;
+            let tag = tag & 0x7f;
+            let subpacket = match tag {
+                2 => Subpacket::SignatureCreationTime(a.read_u32::<BigEndian>()?),
+                3 => Subpacket::SignatureExpirationTime(a.read_u32::<BigEndian>()?),
+                4 => Subpacket::ExportableCertification(a.read_u8()? != 0),
+                5 => {
+                    let level = a.read_u8()?;
+                    let amount = a.read_u8()?;
+                    Subpacket::TrustSignature {
+                        level,
+                        amount,
+                    }
+                },
+                6 => {
+                    let regex = a.strip_suffix(b"\0").unwrap_or(a);
+                    Subpacket::RegularExpression(lossy_string(regex))
+                },
+                7 => Subpacket::Revocable(a.read_u8()? != 0),
+                9 => Subpacket::KeyExpirationTime(a.read_u32::<BigEndian>()?),
+                11 => Subpacket::PreferredSymmetricAlgorithms(a.to_vec()),
+                12 => {
+                    let class = a.read_u8()?;
+                    let algorithm = PublicKeyAlgorithm::from_byte(a.read_u8()?);
+                    let fingerprint = hex::encode_upper(a);
+                    Subpacket::RevocationKey {
+                        class,
+                        algorithm,
+                        fingerprint,
+                    }
+                },
                 16 => {
                     let issuer = a.read_u64::<BigEndian>()?;
-                    let issuer = format!("{:X}", issuer);
-                    Ok(Some(Subpacket::Issuer(issuer)))
+                    let issuer = format!("{:016X}", issuer);
+                    Subpacket::Issuer(issuer)
+                },
+                20 => {
+                    let flags = a.read_u32::<BigEndian>()?;
+                    let name_len = a.read_u16::<BigEndian>()? as usize;
+                    let value_len = a.read_u16::<BigEndian>()? as usize;
+                    if name_len + value_len != a.len() {
+                        bail!("invalid notation length");
+                    }
+                    let (name, value) = a.split_at(name_len);
+                    Subpacket::NotationData {
+                        human_readable: flags & 0x8000_0000 != 0,
+                        name: lossy_string(name),
+                        value: value.to_vec(),
+                    }
+                },
+                21 => Subpacket::PreferredHashAlgorithms(a.to_vec()),
+                22 => Subpacket::PreferredCompressionAlgorithms(a.to_vec()),
+                23 => Subpacket::KeyServerPreferences(a.to_vec()),
+                24 => Subpacket::PreferredKeyServer(lossy_string(a)),
+                25 => Subpacket::PrimaryUserId(a.read_u8()? != 0),
+                26 => Subpacket::PolicyUri(lossy_string(a)),
+                27 => Subpacket::KeyFlags(a.to_vec()),
+                28 => Subpacket::SignersUserId(lossy_string(a)),
+                29 => {
+                    let code = a.read_u8()?;
+                    Subpacket::ReasonForRevocation {
+                        code,
+                        reason: lossy_string(a),
+                    }
+                },
+                30 => Subpacket::Features(a.to_vec()),
+                32 => {
+                    if depth >= MAX_DEPTH {
+                        bail!("embedded signatures are nested too deep");
+                    }
+                    let sig = parse_nested(a, depth + 1)?;
+                    Subpacket::EmbeddedSignature(Box::new(sig))
                 },
                 33 => {
-                    let _v = a.read_u8()?;
-                    let fp = hex::encode_upper(a);
-                    Ok(Some(Subpacket::IssuerFingerprint(fp)))
+                    let version = a.read_u8()?;
+                    let fingerprint = hex::encode_upper(a);
+                    Subpacket::IssuerFingerprint {
+                        version,
+                        fingerprint,
+                    }
                 },
-                _ => Ok(None),
-            }
+                _ => Subpacket::Unknown {
+                    tag,
+                    data: a.to_vec(),
+                },
+            };
+
+            Ok(SignatureSubpacket {
+                critical,
+                subpacket,
+            })
         } else {
             bail!("index out of bounds")
         }
@@ -90,6 +528,7 @@ impl Subpacket {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::packets;
 
     #[test]
     fn parse_signature_issuer() {
@@ -97,8 +536,369 @@ mod tests {
         let sig = parse(bytes).expect("parse");
 
         assert_eq!(sig, Signature {
+            version: 4,
+            sigtype: SignatureType::GenericCertification,
+            pk_algo: PublicKeyAlgorithm::Rsa,
+            hash_algo: HashAlgorithm::Sha256,
+            hash_prefix: [0x64, 0x45],
+            mpis: vec![bytes[bytes.len() - 512..].to_vec()],
+            hashed_subpackets: vec![
+                SignatureSubpacket {
+                    critical: false,
+                    subpacket: Subpacket::IssuerFingerprint {
+                        version: 4,
+                        fingerprint: String::from("903BAB73640EB6D65533EFF3468F122CE8162295"),
+                    },
+                },
+                SignatureSubpacket {
+                    critical: false,
+                    subpacket: Subpacket::SignatureCreationTime(1541196566),
+                },
+            ],
+            unhashed_subpackets: vec![
+                SignatureSubpacket {
+                    critical: false,
+                    subpacket: Subpacket::Issuer(String::from("468F122CE8162295")),
+                },
+            ],
+            created: Some(1541196566),
             keyid: Some(String::from("468F122CE8162295")),
             fingerprint: Some(String::from("903BAB73640EB6D65533EFF3468F122CE8162295")),
+            hashed_data: bytes[..35].to_vec(),
+        });
+    }
+
+    #[test]
+    fn parse_v3_signature() {
+        let key = include_bytes!("../data/freebsd.asc");
+        let key = crate::armor::read_armored(&mut &key[..]).expect("read_armored");
+
+        let sig = crate::Parser::new(key.as_slice())
+            .filter(|(tag, _)| *tag == crate::Tag::Signature)
+            .map(|(_, body)| parse(&body).expect("parse"))
+            .find(|sig| sig.version == 3)
+            .expect("no v3 signature found");
+
+        assert_eq!(sig.sigtype, SignatureType::GenericCertification);
+        assert_eq!(sig.pk_algo, PublicKeyAlgorithm::Rsa);
+        assert_eq!(sig.hash_algo, HashAlgorithm::Md5);
+        assert_eq!(sig.hash_prefix, [0x6b, 0xe6]);
+        assert_eq!(sig.created, Some(1037247179));
+        assert_eq!(sig.keyid.as_deref(), Some("4D56282179050C11"));
+        assert_eq!(sig.fingerprint, None);
+        assert_eq!(sig.mpis.len(), 1);
+        assert_eq!(sig.mpis[0].len(), 128);
+    }
+
+    #[test]
+    fn parse_v3_signature_invalid_hashed_len() {
+        let bytes = b"\x03\x06\x10\x3d\xd3\x1f\xcb\x4d\x56\x28\x21\x79\x05\x0c\x11\x01\x01\x6b\xe6";
+        assert!(parse(bytes).is_err());
+    }
+
+    #[test]
+    fn parse_self_signature_subpackets() {
+        let key = include_bytes!("../data/hans_acker.asc");
+        let key = crate::armor::read_armored(&mut &key[..]).expect("read_armored");
+
+        let (_, body) = crate::Parser::new(key.as_slice())
+            .find(|(tag, _)| *tag == crate::Tag::Signature)
+            .expect("no signature found");
+        let sig = parse(&body).expect("parse");
+
+        assert_eq!(sig.sigtype, SignatureType::PositiveCertification);
+        assert_eq!(sig.created, Some(1538960349));
+        let subpackets = |subpackets: Vec<SignatureSubpacket>| {
+            subpackets.into_iter()
+                .map(|sub| {
+                    assert!(!sub.critical);
+                    sub.subpacket
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(subpackets(sig.unhashed_subpackets), vec![
+            Subpacket::Issuer(String::from("334D08A1D19D963F")),
+        ]);
+        assert_eq!(subpackets(sig.hashed_subpackets), vec![
+            Subpacket::IssuerFingerprint {
+                version: 4,
+                fingerprint: String::from("CB378ED5E1306C1D3785CA81334D08A1D19D963F"),
+            },
+            Subpacket::SignatureCreationTime(1538960349),
+            Subpacket::KeyFlags(vec![0x03]),
+            Subpacket::PreferredSymmetricAlgorithms(vec![9, 8, 7, 2]),
+            Subpacket::PreferredHashAlgorithms(vec![8, 9, 10, 11, 2]),
+            Subpacket::PreferredCompressionAlgorithms(vec![2, 3, 1]),
+            Subpacket::Features(vec![0x01]),
+            Subpacket::KeyServerPreferences(vec![0x80]),
+        ]);
+    }
+
+    fn read_all(mut data: &[u8]) -> Vec<Subpacket> {
+        let mut subpackets = Vec::new();
+        while !data.is_empty() {
+            let sub = Subpacket::read(&mut data, 0).expect("Subpacket::read");
+            subpackets.push(sub.subpacket);
+        }
+        subpackets
+    }
+
+    #[test]
+    fn parse_misc_subpackets() {
+        let data = b"\x05\x03\x00\x01\x51\x80\
+            \x02\x04\x00\
+            \x03\x05\x01\x78\
+            \x1a\x06<[^>]+[@.]example\\.com>$\x00\
+            \x02\x07\x01\
+            \x05\x09\x00\x00\x0e\x10\
+            \x17\x0c\x80\x11\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\x11\x12\x13\x14\
+            \x1d\x14\x80\x00\x00\x00\x00\x10\x00\x04test@example.comabcd\
+            \x18\x18hkps://keys.example.com\
+            \x02\x19\x01\
+            \x15\x1ahttps://example.com/\
+            \x11\x1chans@example.com\
+            \x0c\x1d\x02superseded\
+            \x03\x64\x01\x02";
+
+        assert_eq!(read_all(data), vec![
+            Subpacket::SignatureExpirationTime(86400),
+            Subpacket::ExportableCertification(false),
+            Subpacket::TrustSignature {
+                level: 1,
+                amount: 120,
+            },
+            Subpacket::RegularExpression(String::from("<[^>]+[@.]example\\.com>$")),
+            Subpacket::Revocable(true),
+            Subpacket::KeyExpirationTime(3600),
+            Subpacket::RevocationKey {
+                class: 0x80,
+                algorithm: PublicKeyAlgorithm::Dsa,
+                fingerprint: String::from("0102030405060708090A0B0C0D0E0F1011121314"),
+            },
+            Subpacket::NotationData {
+                human_readable: true,
+                name: String::from("test@example.com"),
+                value: b"abcd".to_vec(),
+            },
+            Subpacket::PreferredKeyServer(String::from("hkps://keys.example.com")),
+            Subpacket::PrimaryUserId(true),
+            Subpacket::PolicyUri(String::from("https://example.com/")),
+            Subpacket::SignersUserId(String::from("hans@example.com")),
+            Subpacket::ReasonForRevocation {
+                code: 2,
+                reason: String::from("superseded"),
+            },
+            Subpacket::Unknown {
+                tag: 100,
+                data: vec![1, 2],
+            },
+        ]);
+    }
+
+    #[test]
+    fn parse_embedded_signature() {
+        let inner = b"\x04\x19\x01\x08\x00\x06\x05\x02\x5b\xba\xab\xdd\x00\x00\x12\x34\x00\x01\x01";
+        let mut data = vec![inner.len() as u8 + 1, 32];
+        data.extend(inner);
+
+        let subpackets = read_all(&data);
+        if let [Subpacket::EmbeddedSignature(sig)] = subpackets.as_slice() {
+            assert_eq!(sig.sigtype, SignatureType::PrimaryKeyBinding);
+            assert_eq!(sig.created, Some(1538960349));
+            assert_eq!(sig.mpis, vec![vec![1]]);
+        } else {
+            panic!("expected embedded signature");
+        }
+    }
+
+    #[test]
+    fn parse_critical_subpackets() {
+        let mut data = &b"\x05\x82\x5b\xba\xab\xdd\x03\xe4\x01\x02"[..];
+
+        let sub = Subpacket::read(&mut data, 0).expect("Subpacket::read");
+        assert_eq!(sub, SignatureSubpacket {
+            critical: true,
+            subpacket: Subpacket::SignatureCreationTime(1538960349),
+        });
+
+        let sub = Subpacket::read(&mut data, 0).expect("Subpacket::read");
+        assert_eq!(sub, SignatureSubpacket {
+            critical: true,
+            subpacket: Subpacket::Unknown {
+                tag: 100,
+                data: vec![1, 2],
+            },
         });
     }
+
+    #[test]
+    fn parse_large_subpackets() {
+        for (len, header) in [(8384, vec![0xe0, 0x00]), (16319, vec![0xfe, 0xff]), (70000, vec![0xff, 0x00, 0x01, 0x11, 0x70])] {
+            let mut data = header;
+            data.push(100);
+            data.extend(vec![0x42; len - 1]);
+            data.extend(b"\x05\x02\x5b\xba\xab\xdd");
+
+            assert_eq!(read_all(&data), vec![
+                Subpacket::Unknown {
+                    tag: 100,
+                    data: vec![0x42; len - 1],
+                },
+                Subpacket::SignatureCreationTime(1538960349),
+            ]);
+        }
+    }
+
+    #[test]
+    fn parse_strict_unknown_critical() {
+        let known = b"\x04\x00\x01\x08\x00\x06\x05\x82\x5b\xba\xab\xdd\x00\x04\x03\x64\x01\x02\x12\x34\x00\x01\x01";
+        let sig = parse_strict(known).expect("parse_strict");
+        assert_eq!(sig.created, Some(1538960349));
+        assert!(!sig.has_unknown_critical_subpackets());
+
+        let unknown = b"\x04\x00\x01\x08\x00\x06\x05\x82\x5b\xba\xab\xdd\x00\x04\x03\xe4\x01\x02\x12\x34\x00\x01\x01";
+        let sig = parse(unknown).expect("parse");
+        assert!(sig.has_unknown_critical_subpackets());
+        assert!(parse_strict(unknown).is_err());
+    }
+
+    #[test]
+    fn parse_strict_embedded_unknown_critical() {
+        let inner = b"\x04\x19\x01\x08\x00\x04\x03\xe4\x01\x02\x00\x00\x12\x34\x00\x01\x01";
+        let mut outer = b"\x04\x18\x01\x08\x00\x00".to_vec();
+        outer.extend(&(inner.len() as u16 + 2).to_be_bytes());
+        outer.extend(&[inner.len() as u8 + 1, 32]);
+        outer.extend(inner);
+        outer.extend(b"\x12\x34\x00\x01\x01");
+
+        let sig = parse(&outer).expect("parse");
+        assert!(sig.has_unknown_critical_subpackets());
+        assert!(parse_strict(&outer).is_err());
+    }
+
+    #[test]
+    fn prefer_hashed_subpackets() {
+        // hashed: creation time, issuer 0123456789ABCDEF
+        // unhashed: creation time, issuer FEDCBA9876543210, issuer fingerprint
+        let bytes = b"\x04\x00\x01\x08\
+            \x00\x10\x05\x02\x5b\xba\xab\xdd\x09\x10\x01\x23\x45\x67\x89\xab\xcd\xef\
+            \x00\x27\x05\x02\x00\x00\x00\x01\x09\x10\xfe\xdc\xba\x98\x76\x54\x32\x10\
+            \x16\x21\x04\xcb\x37\x8e\xd5\xe1\x30\x6c\x1d\x37\x85\xca\x81\x33\x4d\x08\xa1\xd1\x9d\x96\x3f\
+            \x12\x34\x00\x01\x01";
+        let sig = parse(bytes).expect("parse");
+
+        assert_eq!(sig.hashed_subpackets.len(), 2);
+        assert_eq!(sig.unhashed_subpackets.len(), 3);
+        assert_eq!(sig.created, Some(1538960349));
+        assert_eq!(sig.keyid.as_deref(), Some("0123456789ABCDEF"));
+        assert_eq!(sig.fingerprint.as_deref(), Some("CB378ED5E1306C1D3785CA81334D08A1D19D963F"));
+        assert_eq!(sig.find_hashed_subpacket(|sub| match sub {
+            Subpacket::IssuerFingerprint { fingerprint, .. } => Some(fingerprint),
+            _ => None,
+        }), None);
+    }
+
+    #[test]
+    fn issuer_keyid_from_fingerprint() {
+        let mut bytes = b"\x04\x00\x01\x08\x00\x23\x22\x21\x05".to_vec();
+        bytes.extend(&hex::decode("CB186C4F0609A697E4D52DFA6C722B0C1F1E27C18A56708F6525EC27BAD9ACC9").unwrap());
+        bytes.extend(b"\x00\x00\x12\x34\x00\x01\x01");
+        let sig = parse(&bytes).expect("parse");
+        assert_eq!(sig.keyid, None);
+        assert_eq!(sig.issuer_keyid(), Some("CB186C4F0609A697"));
+
+        let bytes = b"\x04\x00\x01\x08\x00\x17\x16\x21\x04\xcb\x37\x8e\xd5\xe1\x30\x6c\x1d\x37\x85\xca\x81\x33\x4d\x08\xa1\xd1\x9d\x96\x3f\x00\x00\x12\x34\x00\x01\x01";
+        let sig = parse(bytes).expect("parse");
+        assert_eq!(sig.issuer_keyid(), Some("334D08A1D19D963F"));
+    }
+
+    #[test]
+    fn ignore_unhashed_creation_time() {
+        let bytes = b"\x04\x00\x01\x08\x00\x00\x00\x06\x05\x02\x5b\xba\xab\xdd\x12\x34\x00\x01\x01";
+        let sig = parse(bytes).expect("parse");
+        assert_eq!(sig.created, None);
+    }
+
+    #[test]
+    fn hash_input_v4_certification() {
+        use sha2::{Digest, Sha256};
+
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let userid = &packets[1].1;
+        let sig = parse(&packets[2].1).expect("parse");
+
+        let input = sig.hash_input(&SignedData::UserId { key, userid }).expect("hash_input");
+        let len = sig.hashed_data.len() as u32;
+        assert!(input.ends_with(&[&[0x04, 0xff][..], &len.to_be_bytes()].concat()));
+        let hash = Sha256::digest(&input);
+        assert_eq!(hash[..2], sig.hash_prefix);
+    }
+
+    #[test]
+    fn hash_input_v4_subkey_binding() {
+        use sha2::{Digest, Sha256};
+
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let subkey = &packets[3].1;
+        let sig = parse(&packets[4].1).expect("parse");
+        assert_eq!(sig.sigtype, SignatureType::SubkeyBinding);
+
+        let input = sig.hash_input(&SignedData::Subkey { key, subkey }).expect("hash_input");
+        let hash = Sha256::digest(&input);
+        assert_eq!(hash[..2], sig.hash_prefix);
+
+        // the wrong data doesn't match
+        let input = sig.hash_input(&SignedData::Key(key)).expect("hash_input");
+        let hash = Sha256::digest(&input);
+        assert_ne!(hash[..2], sig.hash_prefix);
+    }
+
+    #[test]
+    fn hash_input_v3_certification() {
+        use md5::{Digest, Md5};
+
+        let packets = packets(include_bytes!("../data/freebsd.asc"));
+        let key = &packets[0].1;
+        let (idx, sig) = packets.iter()
+            .enumerate()
+            .filter(|(_, (tag, _))| *tag == crate::Tag::Signature)
+            .map(|(idx, (_, body))| (idx, parse(body).expect("parse")))
+            .find(|(_, sig)| sig.version == 3)
+            .expect("no v3 signature found");
+        let userid = packets[..idx].iter()
+            .rev()
+            .find(|(tag, _)| *tag == crate::Tag::UserID)
+            .map(|(_, body)| body)
+            .expect("no userid found");
+
+        let input = sig.hash_input(&SignedData::UserId { key, userid }).expect("hash_input");
+        assert!(input.ends_with(&sig.hashed_data));
+        assert_eq!(sig.hashed_data.len(), 5);
+        let hash = Md5::digest(&input);
+        assert_eq!(hash[..2], sig.hash_prefix);
+    }
+
+    fn nested_signature(depth: usize) -> Vec<u8> {
+        let mut sig = b"\x04\x19\x01\x08\x00\x00\x00\x00\x12\x34".to_vec();
+        for _ in 0..depth {
+            let mut outer = b"\x04\x19\x01\x08".to_vec();
+            let len = sig.len() + 6;
+            outer.extend(&(len as u16).to_be_bytes());
+            outer.push(0xff);
+            outer.extend(&(sig.len() as u32 + 1).to_be_bytes());
+            outer.push(32);
+            outer.extend(&sig);
+            outer.extend(b"\x00\x00\x12\x34");
+            sig = outer;
+        }
+        sig
+    }
+
+    #[test]
+    fn parse_deeply_nested_signature() {
+        parse(&nested_signature(MAX_DEPTH)).expect("parse");
+        assert!(parse(&nested_signature(MAX_DEPTH + 1)).is_err());
+    }
 }
@@ -0,0 +1,19 @@
+//! Helpers shared by the unit tests
+use crate::armor::read_armored;
+use crate::packet::Tag;
+use crate::Parser;
+
+/// Unarmor a test fixture and split it into packets
+pub fn packets(armored: &[u8]) -> Vec<(Tag, Vec<u8>)> {
+    let data = read_armored(&mut &armored[..]).expect("read_armored");
+    Parser::new(data.as_slice()).collect()
+}
+
+/// Like `packets`, but only return the bodies of packets with one of the
+/// given tags
+pub fn bodies(armored: &[u8], tags: &[Tag]) -> Vec<Vec<u8>> {
+    packets(armored).into_iter()
+        .filter(|(tag, _)| tags.contains(tag))
+        .map(|(_, body)| body)
+        .collect()
+}
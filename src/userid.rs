@@ -0,0 +1,140 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+/// A user ID, split into the conventional `Name (Comment) <email>` parts.
+/// The format isn't enforced by the spec, so every part is optional and
+/// the raw packet body is kept for hashing.
+// https://tools.ietf.org/html/rfc4880#section-5.11
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserId {
+    pub raw: Vec<u8>,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub email: Option<String>,
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+fn looks_like_email(s: &str) -> bool {
+    s.contains('@') && !s.contains(char::is_whitespace)
+}
+
+/// Split `s` into the text in front of the last `<...>` and the text inside.
+/// A missing closing bracket is tolerated.
+fn split_email(s: &str) -> Option<(&str, &str)> {
+    let start = s.rfind('<')?;
+    let inner = &s[start + 1..];
+    let inner = match inner.find('>') {
+        Some(end) => &inner[..end],
+        None => inner,
+    };
+    let inner = inner.trim();
+    if looks_like_email(inner) {
+        Some((s[..start].trim_end_matches('<'), inner))
+    } else {
+        None
+    }
+}
+
+fn split_comment(s: &str) -> (&str, Option<&str>) {
+    let s = s.trim();
+    if let Some(rest) = s.strip_suffix(')') {
+        if let Some(start) = rest.rfind('(') {
+            return (&rest[..start], Some(&rest[start + 1..]));
+        }
+    }
+    (s, None)
+}
+
+impl UserId {
+    pub fn parse(body: &[u8]) -> UserId {
+        let text = String::from_utf8_lossy(body);
+        let text = text.trim();
+
+        let (rest, email) = if let Some((rest, email)) = split_email(text) {
+            (rest, Some(email))
+        } else if looks_like_email(text) {
+            ("", Some(text))
+        } else {
+            (text, None)
+        };
+        let (name, comment) = split_comment(rest);
+
+        UserId {
+            raw: body.to_vec(),
+            name: non_empty(name),
+            comment: comment.and_then(non_empty),
+            email: email.map(String::from),
+        }
+    }
+
+    /// The email address in lowercase, for matching user IDs by address
+    pub fn normalized_email(&self) -> Option<String> {
+        self.email.as_ref()
+            .map(|email| email.to_lowercase())
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_parts(uid: &str, name: Option<&str>, comment: Option<&str>, email: Option<&str>) {
+        let uid = UserId::parse(uid.as_bytes());
+        assert_eq!((uid.name.as_deref(), uid.comment.as_deref(), uid.email.as_deref()), (name, comment, email));
+    }
+
+    #[test]
+    fn parse_full_userid() {
+        let uid = UserId::parse(b"Hans Acker (example comment) <hans.acker@example.com>");
+        assert_eq!(uid, UserId {
+            raw: b"Hans Acker (example comment) <hans.acker@example.com>".to_vec(),
+            name: Some(String::from("Hans Acker")),
+            comment: Some(String::from("example comment")),
+            email: Some(String::from("hans.acker@example.com")),
+        });
+        assert_eq!(uid.to_string(), "Hans Acker (example comment) <hans.acker@example.com>");
+    }
+
+    #[test]
+    fn parse_userid_variants() {
+        assert_parts("FreeBSD Security Officer <security-officer@FreeBSD.org>", Some("FreeBSD Security Officer"), None, Some("security-officer@FreeBSD.org"));
+        assert_parts("<foo@example.com>", None, None, Some("foo@example.com"));
+        assert_parts("foo@example.com", None, None, Some("foo@example.com"));
+        assert_parts("Foo Bar", Some("Foo Bar"), None, None);
+        assert_parts("Foo Bar (work)", Some("Foo Bar"), Some("work"), None);
+        assert_parts("Foo Bar <foo@example.com", Some("Foo Bar"), None, Some("foo@example.com"));
+        assert_parts("Foo Bar <<foo@example.com>>", Some("Foo Bar"), None, Some("foo@example.com"));
+        assert_parts("Foo <old@example.com> <new@example.com>", Some("Foo <old@example.com>"), None, Some("new@example.com"));
+        assert_parts("Foo <not an email>", Some("Foo <not an email>"), None, None);
+        assert_parts("", None, None, None);
+    }
+
+    #[test]
+    fn parse_invalid_utf8() {
+        let uid = UserId::parse(b"J\xf6rg (\xff) <joerg@example.com>");
+        assert_eq!(uid.name.as_deref(), Some("J\u{fffd}rg"));
+        assert_eq!(uid.comment.as_deref(), Some("\u{fffd}"));
+        assert_eq!(uid.email.as_deref(), Some("joerg@example.com"));
+    }
+
+    #[test]
+    fn normalized_email() {
+        let uid = UserId::parse(b"FreeBSD Security Officer <Security-Officer@FreeBSD.org>");
+        assert_eq!(uid.normalized_email().as_deref(), Some("security-officer@freebsd.org"));
+        assert_eq!(UserId::parse(b"Foo").normalized_email(), None);
+    }
+}
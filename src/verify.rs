@@ -0,0 +1,191 @@
+use crate::algorithm::HashAlgorithm;
+use crate::errors::*;
+use crate::pubkey::PublicKeyMaterial;
+
+/// Cryptographic backend for signature verification. This crate only
+/// assembles the data covered by a signature, hashing it and checking the
+/// signature MPIs against the public key is up to the implementation.
+pub trait Verifier {
+    /// Verify `signature` over `data`, which is the complete hash input as
+    /// returned by `Signature::hash_input`.
+    fn verify(&self,
+              hash: &HashAlgorithm,
+              key: &PublicKeyMaterial,
+              data: &[u8],
+              signature: &[Vec<u8>]) -> Result<bool>;
+}
+
+/// Default verifier using the `rsa` crate, supports PKCS#1 v1.5 RSA
+/// signatures with MD5, SHA1 and SHA2 hashes.
+#[cfg(feature = "rsa")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultVerifier;
+
+#[cfg(feature = "rsa")]
+mod rsa_impl {
+    use super::*;
+    use md5::Md5;
+    use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+    use rsa::traits::PublicKeyParts;
+    use sha1::{Digest, Sha1};
+    use sha2::{Sha224, Sha256, Sha384, Sha512};
+
+    // https://tools.ietf.org/html/rfc4880#section-5.2.2
+    fn digest_info(hash: &HashAlgorithm, data: &[u8]) -> Result<(&'static [u8], Vec<u8>)> {
+        Ok(match hash {
+            HashAlgorithm::Md5 => (
+                &[0x30, 0x20, 0x30, 0x0c, 0x06, 0x08, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x05, 0x05, 0x00, 0x04, 0x10],
+                Md5::digest(data).to_vec(),
+            ),
+            HashAlgorithm::Sha1 => (
+                &[0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14],
+                Sha1::digest(data).to_vec(),
+            ),
+            HashAlgorithm::Sha224 => (
+                &[0x30, 0x2d, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x04, 0x05, 0x00, 0x04, 0x1c],
+                Sha224::digest(data).to_vec(),
+            ),
+            HashAlgorithm::Sha256 => (
+                &[0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20],
+                Sha256::digest(data).to_vec(),
+            ),
+            HashAlgorithm::Sha384 => (
+                &[0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30],
+                Sha384::digest(data).to_vec(),
+            ),
+            HashAlgorithm::Sha512 => (
+                &[0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04, 0x40],
+                Sha512::digest(data).to_vec(),
+            ),
+            hash => bail!("Unsupported hash algorithm: {:?}", hash),
+        })
+    }
+
+    impl Verifier for DefaultVerifier {
+        fn verify(&self,
+                  hash: &HashAlgorithm,
+                  key: &PublicKeyMaterial,
+                  data: &[u8],
+                  signature: &[Vec<u8>]) -> Result<bool> {
+            let (n, e) = match key {
+                PublicKeyMaterial::Rsa { n, e } => (n, e),
+                _ => bail!("Unsupported public key algorithm"),
+            };
+            let sig = match signature {
+                [sig] => sig,
+                _ => bail!("Expected exactly one mpi in rsa signature"),
+            };
+
+            let key = RsaPublicKey::new(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e))
+                .context("Invalid rsa public key")?;
+
+            // leading zeros are stripped from the mpi
+            let size = key.size();
+            if sig.len() > size {
+                return Ok(false);
+            }
+            let mut padded = vec![0; size - sig.len()];
+            padded.extend(sig);
+
+            let (prefix, hashed) = digest_info(hash, data)?;
+            let scheme = Pkcs1v15Sign {
+                hash_len: Some(hashed.len()),
+                prefix: prefix.into(),
+            };
+            Ok(key.verify(scheme, &hashed, &padded).is_ok())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::PublicKeyAlgorithm;
+    use crate::pubkey;
+    use crate::signature::{self, SignedData};
+    use crate::test_util::packets;
+
+    struct Recorder(std::cell::RefCell<Vec<Vec<u8>>>);
+
+    impl Verifier for Recorder {
+        fn verify(&self,
+                  hash: &HashAlgorithm,
+                  key: &PublicKeyMaterial,
+                  data: &[u8],
+                  signature: &[Vec<u8>]) -> Result<bool> {
+            assert_eq!(*hash, HashAlgorithm::Sha256);
+            assert!(matches!(key, PublicKeyMaterial::Rsa { .. }));
+            assert_eq!(signature.len(), 1);
+            self.0.borrow_mut().push(data.to_vec());
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn verifier_receives_hash_input() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let userid = &packets[1].1;
+        let sig = signature::parse(&packets[2].1).unwrap();
+        let pubkey = pubkey::parse(key).unwrap();
+
+        let recorder = Recorder(Default::default());
+        let data = SignedData::UserId { key, userid };
+        assert!(sig.verify(&recorder, &pubkey, &data).unwrap());
+
+        let recorded = recorder.0.into_inner();
+        assert_eq!(recorded, vec![sig.hash_input(&data).unwrap()]);
+    }
+
+    #[test]
+    fn verifier_not_called_for_wrong_algorithm() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let sig = signature::parse(&packets[2].1).unwrap();
+        let mut pubkey = pubkey::parse(key).unwrap();
+        pubkey.algorithm = PublicKeyAlgorithm::Dsa;
+
+        let recorder = Recorder(Default::default());
+        assert!(!sig.verify(&recorder, &pubkey, &SignedData::Key(key)).unwrap());
+        assert!(recorder.0.into_inner().is_empty());
+    }
+
+    #[test]
+    fn verifier_not_called_for_unknown_critical_subpacket() {
+        use crate::signature::{SignatureSubpacket, Subpacket};
+
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let userid = &packets[1].1;
+        let mut sig = signature::parse(&packets[2].1).unwrap();
+        let pubkey = pubkey::parse(key).unwrap();
+        sig.hashed_subpackets.push(SignatureSubpacket {
+            critical: true,
+            subpacket: Subpacket::Unknown {
+                tag: 100,
+                data: vec![1, 2],
+            },
+        });
+
+        let recorder = Recorder(Default::default());
+        assert!(!sig.verify(&recorder, &pubkey, &SignedData::UserId { key, userid }).unwrap());
+        assert!(recorder.0.into_inner().is_empty());
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn default_verifier_self_signatures() {
+        let packets = packets(include_bytes!("../data/hans_acker.asc"));
+        let key = &packets[0].1;
+        let userid = &packets[1].1;
+        let subkey = &packets[3].1;
+        let pubkey = pubkey::parse(key).unwrap();
+
+        let sig = signature::parse(&packets[2].1).unwrap();
+        assert!(sig.verify(&DefaultVerifier, &pubkey, &SignedData::UserId { key, userid }).unwrap());
+        assert!(!sig.verify(&DefaultVerifier, &pubkey, &SignedData::UserId { key, userid: b"Mallory" }).unwrap());
+
+        let sig = signature::parse(&packets[4].1).unwrap();
+        assert!(sig.verify(&DefaultVerifier, &pubkey, &SignedData::Subkey { key, subkey }).unwrap());
+    }
+}